@@ -1,18 +1,319 @@
 use anchor_lang::prelude::*;
 
+/// Program ID of the deployed CoolRouter program.
+pub const COOLROUTER_PROGRAM_ID: Pubkey = pubkey!("CATsZNcHms98EcQo1qzGcA3XLPf47NLhQC5g2cRe19Gu");
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct Message {
     pub role: String,
     pub content: String,
 }
 
+/// `temperature` (0..=2000) and `top_p` (0..=1000) are scaled by 1000.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct GenerationParams {
+    pub temperature: u16,
+    pub max_tokens: u32,
+    pub top_p: u16,
+    pub seed: Option<u64>,
+}
+
+/// An additional provider/model pair for cross-provider consensus.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ProviderTarget {
+    pub provider: String,
+    pub model_id: String,
+}
+
+pub const CANONICAL_MESSAGES_VERSION: u8 = 1;
+
+/// Canonical byte encoding of a conversation, hashed with sha256 to form the
+/// request's `conversation_hash`. Version 1 is:
+///
+/// `version: u8 || count: u32 LE || for each message: role_len: u32 LE || role ||
+/// content_len: u32 LE || content`
+///
+/// Strings are the raw UTF-8 bytes with no normalization. Any change to this
+/// layout must bump `CANONICAL_MESSAGES_VERSION`.
+pub fn canonical_messages_bytes(messages: &[Message]) -> Vec<u8> {
+    let mut bytes = vec![CANONICAL_MESSAGES_VERSION];
+    bytes.extend_from_slice(&(messages.len() as u32).to_le_bytes());
+    for message in messages {
+        bytes.extend_from_slice(&(message.role.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(message.role.as_bytes());
+        bytes.extend_from_slice(&(message.content.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(message.content.as_bytes());
+    }
+    bytes
+}
+
+/// Provider-reported details an oracle votes on together with the response.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ResponseMeta {
+    pub model_id: String,
+    pub finish_reason: String,
+}
+
+/// Preimage of the response hash oracles vote on: the Borsh encoding of
+/// `(request_id, response, metadata)`, which is
+///
+/// `request_id_len: u32 LE || request_id || response_len: u32 LE || response ||
+/// model_id_len: u32 LE || model_id || finish_reason_len: u32 LE || finish_reason`
+///
+/// Oracles that agree on the response and metadata produce the same bytes
+/// whatever else they submit alongside, such as the content type or an
+/// attestation.
+///
+/// For requests with extra `providers`, pass an empty `model_id`: oracles
+/// answering through different targets must hash the same bytes, and the
+/// vote's `provider_index` carries which target each one used.
+pub fn canonical_response_bytes(request_id: &str, response: &[u8], metadata: &ResponseMeta) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(
+        16 + request_id.len() + response.len() + metadata.model_id.len() + metadata.finish_reason.len(),
+    );
+    for field in [
+        request_id.as_bytes(),
+        response,
+        metadata.model_id.as_bytes(),
+        metadata.finish_reason.as_bytes(),
+    ] {
+        bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(field);
+    }
+    bytes
+}
+
+/// Values of the `detected_format` tag the router appends to callback data
+/// when format detection is enabled.
+pub const FORMAT_UNDETECTED: u8 = 0;
+pub const FORMAT_TEXT: u8 = 1;
+pub const FORMAT_JSON: u8 = 2;
+pub const FORMAT_BINARY: u8 = 3;
+
+/// Values of the `content_type` tag the fulfilling oracle declares, appended
+/// last to callback data.
+pub const CONTENT_TYPE_TEXT: u8 = 0;
+pub const CONTENT_TYPE_JSON: u8 = 1;
+pub const CONTENT_TYPE_BINARY: u8 = 2;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub enum ResponseConstraint {
+    OneOf(Vec<String>),
+    NumericRange(u64, u64),
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum MultiWinnerPolicy {
+    FirstToThreshold,
+    #[default]
+    HighestCount,
+    RejectAmbiguous,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum VoteWeighting {
+    #[default]
+    Unweighted,
+    Stake,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum RoundingMode {
+    Floor,
+    Ceil,
+    #[default]
+    Exact,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum RewardMode {
+    #[default]
+    WinnersOnly,
+    TopCluster,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum TieBreak {
+    #[default]
+    Lexicographic,
+    EarliestFirstVote,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum ResponseKind {
+    #[default]
+    Text,
+    ToolCall,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum NormalizeMode {
+    #[default]
+    Raw,
+    TrimTrailingWhitespace,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum ApprovalKind {
+    #[default]
+    Threshold,
+    StrictMajority,
+    Plurality,
+}
+
+/// Seed of the router's result cache PDA `[b"cache", key]`: sha256 of the
+/// Borsh encoding of `(conversation_hash, provider, model_id, providers,
+/// generation_params, response_constraint, normalize, response_kind)`. A
+/// cached response only serves requests that agree on all of them.
+#[allow(clippy::too_many_arguments)]
+pub fn result_cache_key(
+    conversation_hash: &[u8; 32],
+    provider: &str,
+    model_id: &str,
+    providers: &[ProviderTarget],
+    generation_params: &Option<GenerationParams>,
+    response_constraint: &Option<ResponseConstraint>,
+    normalize: &NormalizeMode,
+    response_kind: &ResponseKind,
+) -> Result<[u8; 32]> {
+    use sha2::{Digest, Sha256};
+    let bytes = (
+        conversation_hash,
+        provider,
+        model_id,
+        providers,
+        generation_params,
+        response_constraint,
+        normalize,
+        response_kind,
+    )
+        .try_to_vec()?;
+    Ok(Sha256::digest(bytes).into())
+}
+
+/// Consensus used by `create_request_simple` unless `with_consensus` overrides it.
+pub const DEFAULT_MIN_VOTES: u8 = 3;
+pub const DEFAULT_APPROVAL_THRESHOLD: u8 = 66;
+
+/// Consumer instruction the router calls on fulfillment unless
+/// `with_callback_ix_name` names another.
+pub const DEFAULT_CALLBACK_IX_NAME: &str = "llm_callback";
+
+/// Everything the router's `create_request` takes besides the request ID, in
+/// the order it deserializes them.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct CreateRequestParams {
+    pub provider: String,
+    pub model_id: String,
+    pub messages: Vec<Message>,
+    pub min_votes: u8,
+    pub approval_threshold: u8,
+    pub tie_break: TieBreak,
+    pub require_content_entropy: bool,
+    pub approval_kind: ApprovalKind,
+    pub require_attestation: bool,
+    pub reward_lamports: u64,
+    pub fulfiller_bonus_bps: u16,
+    pub consumer_cooldown_seconds: i64,
+    pub expiry_seconds: i64,
+    pub normalize: NormalizeMode,
+    pub response_kind: ResponseKind,
+    pub notify_only: bool,
+    pub reward_mode: RewardMode,
+    pub encrypt_to: Option<[u8; 32]>,
+    pub min_participation: u8,
+    pub fallback_model_id: Option<String>,
+    pub fee_per_response_byte: u64,
+    pub trusted_oracle: Option<Pubkey>,
+    pub keeper_reward_lamports: u64,
+    pub pow_difficulty: u8,
+    pub expires_at_slot: u64,
+    pub sanitize_content: bool,
+    pub allow_cache: bool,
+    pub cache_ttl_seconds: i64,
+    pub allowed_oracles: Option<Vec<Pubkey>>,
+    pub detect_format: bool,
+    pub threshold_rounding: RoundingMode,
+    pub response_constraint: Option<ResponseConstraint>,
+    pub early_completion_margin: u8,
+    pub store_response_onchain: bool,
+    pub vote_weighting: VoteWeighting,
+    pub challenge_period_seconds: i64,
+    pub rich_distribution_callback: bool,
+    pub providers: Vec<ProviderTarget>,
+    pub multi_winner_policy: MultiWinnerPolicy,
+    pub generation_params: Option<GenerationParams>,
+    pub voting_window_seconds: i64,
+    pub callback_ix_name: String,
+}
+
+/// Builds and sends a `create_request` CPI. The consensus can be set on the
+/// builder:
+///
+/// ```ignore
+/// CoolRouterCPI::new(request_pda, authority, caller_program, system_program, config, event_counter, coolrouter::ID)
+///     .add_callback_accounts(callback_accounts)
+///     .with_consensus(5, 80)
+///     .create_request_simple(request_id, "openai".to_string(), "gpt-4o".to_string(), messages)?;
+/// ```
+///
+/// Without `with_consensus`, `create_request_simple` asks for
+/// `DEFAULT_MIN_VOTES` votes at `DEFAULT_APPROVAL_THRESHOLD` percent:
+///
+/// ```ignore
+/// CoolRouterCPI::new(request_pda, authority, caller_program, system_program, config, event_counter, coolrouter::ID)
+///     .create_request_simple(request_id, "openai".to_string(), "gpt-4o".to_string(), messages)?;
+/// ```
 pub struct CoolRouterCPI<'info> {
     pub request_pda: AccountInfo<'info>,
     pub authority: AccountInfo<'info>,
     pub caller_program: AccountInfo<'info>,
     pub system_program: AccountInfo<'info>,
+    pub config: AccountInfo<'info>,
+    pub event_counter: AccountInfo<'info>,
     pub coolrouter_program: Pubkey,
     pub callback_accounts: Vec<AccountInfo<'info>>,
+    pub tie_break: TieBreak,
+    pub require_content_entropy: bool,
+    pub approval_kind: ApprovalKind,
+    pub require_attestation: bool,
+    pub reward_lamports: u64,
+    pub fulfiller_bonus_bps: u16,
+    pub consumer_cooldown_seconds: i64,
+    pub expiry_seconds: i64,
+    pub normalize: NormalizeMode,
+    pub response_kind: ResponseKind,
+    pub notify_only: bool,
+    pub reward_mode: RewardMode,
+    pub encrypt_to: Option<[u8; 32]>,
+    pub min_participation: u8,
+    pub fallback_model_id: Option<String>,
+    pub fee_per_response_byte: u64,
+    pub trusted_oracle: Option<Pubkey>,
+    pub registry_account: Option<AccountInfo<'info>>,
+    pub keeper_reward_lamports: u64,
+    pub pow_difficulty: u8,
+    pub expires_at_slot: u64,
+    pub sanitize_content: bool,
+    pub allow_cache: bool,
+    pub cache_ttl_seconds: i64,
+    pub cache_account: Option<AccountInfo<'info>>,
+    pub allowed_oracles: Option<Vec<Pubkey>>,
+    pub detect_format: bool,
+    pub threshold_rounding: RoundingMode,
+    pub response_constraint: Option<ResponseConstraint>,
+    pub early_completion_margin: u8,
+    pub store_response_onchain: bool,
+    pub vote_weighting: VoteWeighting,
+    pub challenge_period_seconds: i64,
+    pub rich_distribution_callback: bool,
+    pub providers: Vec<ProviderTarget>,
+    pub multi_winner_policy: MultiWinnerPolicy,
+    pub generation_params: Option<GenerationParams>,
+    pub voting_window_seconds: i64,
+    pub min_votes: u8,
+    pub approval_threshold: u8,
+    pub callback_ix_name: String,
 }
 
 impl<'info> CoolRouterCPI<'info> {
@@ -21,6 +322,8 @@ impl<'info> CoolRouterCPI<'info> {
         authority: AccountInfo<'info>,
         caller_program: AccountInfo<'info>,
         system_program: AccountInfo<'info>,
+        config: AccountInfo<'info>,
+        event_counter: AccountInfo<'info>,
         coolrouter_program: Pubkey,
     ) -> Self {
         Self {
@@ -28,8 +331,51 @@ impl<'info> CoolRouterCPI<'info> {
             authority,
             caller_program,
             system_program,
+            config,
+            event_counter,
             coolrouter_program,
             callback_accounts: Vec::new(),
+            tie_break: TieBreak::default(),
+            require_content_entropy: false,
+            approval_kind: ApprovalKind::default(),
+            require_attestation: false,
+            reward_lamports: 0,
+            fulfiller_bonus_bps: 0,
+            consumer_cooldown_seconds: 0,
+            expiry_seconds: 0,
+            normalize: NormalizeMode::default(),
+            response_kind: ResponseKind::default(),
+            notify_only: false,
+            reward_mode: RewardMode::default(),
+            encrypt_to: None,
+            min_participation: 0,
+            fallback_model_id: None,
+            fee_per_response_byte: 0,
+            trusted_oracle: None,
+            registry_account: None,
+            keeper_reward_lamports: 0,
+            pow_difficulty: 0,
+            expires_at_slot: 0,
+            sanitize_content: false,
+            allow_cache: false,
+            cache_ttl_seconds: 0,
+            cache_account: None,
+            allowed_oracles: None,
+            detect_format: false,
+            threshold_rounding: RoundingMode::Exact,
+            response_constraint: None,
+            early_completion_margin: 0,
+            store_response_onchain: false,
+            vote_weighting: VoteWeighting::Unweighted,
+            challenge_period_seconds: 0,
+            rich_distribution_callback: false,
+            providers: Vec::new(),
+            multi_winner_policy: MultiWinnerPolicy::HighestCount,
+            generation_params: None,
+            voting_window_seconds: 0,
+            min_votes: DEFAULT_MIN_VOTES,
+            approval_threshold: DEFAULT_APPROVAL_THRESHOLD,
+            callback_ix_name: DEFAULT_CALLBACK_IX_NAME.to_string(),
         }
     }
 
@@ -43,6 +389,228 @@ impl<'info> CoolRouterCPI<'info> {
         self
     }
 
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    pub fn with_content_entropy_check(mut self, require_content_entropy: bool) -> Self {
+        self.require_content_entropy = require_content_entropy;
+        self
+    }
+
+    pub fn with_approval_kind(mut self, approval_kind: ApprovalKind) -> Self {
+        self.approval_kind = approval_kind;
+        self
+    }
+
+    pub fn with_attestation(mut self, require_attestation: bool) -> Self {
+        self.require_attestation = require_attestation;
+        self
+    }
+
+    pub fn with_reward(mut self, reward_lamports: u64, fulfiller_bonus_bps: u16) -> Self {
+        self.reward_lamports = reward_lamports;
+        self.fulfiller_bonus_bps = fulfiller_bonus_bps;
+        self
+    }
+
+    pub fn with_consumer_cooldown(mut self, consumer_cooldown_seconds: i64) -> Self {
+        self.consumer_cooldown_seconds = consumer_cooldown_seconds;
+        self
+    }
+
+    pub fn with_expiry(mut self, expiry_seconds: i64) -> Self {
+        self.expiry_seconds = expiry_seconds;
+        self
+    }
+
+    pub fn with_normalize(mut self, normalize: NormalizeMode) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    pub fn with_response_kind(mut self, response_kind: ResponseKind) -> Self {
+        self.response_kind = response_kind;
+        self
+    }
+
+    pub fn with_notify_only(mut self, notify_only: bool) -> Self {
+        self.notify_only = notify_only;
+        self
+    }
+
+    pub fn with_reward_mode(mut self, reward_mode: RewardMode) -> Self {
+        self.reward_mode = reward_mode;
+        self
+    }
+
+    pub fn with_encrypt_to(mut self, encrypt_to: Option<[u8; 32]>) -> Self {
+        self.encrypt_to = encrypt_to;
+        self
+    }
+
+    pub fn with_min_participation(mut self, min_participation: u8) -> Self {
+        self.min_participation = min_participation;
+        self
+    }
+
+    pub fn with_fallback_model(mut self, fallback_model_id: Option<String>) -> Self {
+        self.fallback_model_id = fallback_model_id;
+        self
+    }
+
+    pub fn with_response_fee(mut self, fee_per_response_byte: u64) -> Self {
+        self.fee_per_response_byte = fee_per_response_byte;
+        self
+    }
+
+    /// Lets `trusted_oracle` complete voting alone. The router checks it is an
+    /// active oracle in the registry PDA at `[b"registry"]`, passed as `registry`.
+    pub fn with_trusted_oracle(
+        mut self,
+        trusted_oracle: Pubkey,
+        registry: AccountInfo<'info>,
+    ) -> Self {
+        self.trusted_oracle = Some(trusted_oracle);
+        self.registry_account = Some(registry);
+        self
+    }
+
+    pub fn with_keeper_reward(mut self, keeper_reward_lamports: u64) -> Self {
+        self.keeper_reward_lamports = keeper_reward_lamports;
+        self
+    }
+
+    pub fn with_pow_difficulty(mut self, pow_difficulty: u8) -> Self {
+        self.pow_difficulty = pow_difficulty;
+        self
+    }
+
+    pub fn with_slot_expiry(mut self, expires_at_slot: u64) -> Self {
+        self.expires_at_slot = expires_at_slot;
+        self
+    }
+
+    /// Rejects messages containing the router's configured forbidden substrings.
+    /// The router's global config must be initialized.
+    pub fn with_content_sanitization(mut self) -> Self {
+        self.sanitize_content = true;
+        self
+    }
+
+    /// Completes the request immediately from the router's result cache PDA at
+    /// `[b"cache", result_cache_key(..)]` if it holds a response for the same
+    /// model no older than `ttl_seconds`. Allowlisted, trusted-oracle and
+    /// encrypted requests read the cache but never write to it.
+    pub fn with_result_cache(mut self, cache: AccountInfo<'info>, ttl_seconds: i64) -> Self {
+        self.allow_cache = true;
+        self.cache_ttl_seconds = ttl_seconds;
+        self.cache_account = Some(cache);
+        self
+    }
+
+    pub fn with_allowed_oracles(mut self, allowed_oracles: Option<Vec<Pubkey>>) -> Self {
+        self.allowed_oracles = allowed_oracles;
+        self
+    }
+
+    pub fn with_format_detection(mut self, detect_format: bool) -> Self {
+        self.detect_format = detect_format;
+        self
+    }
+
+    pub fn with_threshold_rounding(mut self, threshold_rounding: RoundingMode) -> Self {
+        self.threshold_rounding = threshold_rounding;
+        self
+    }
+
+    pub fn with_response_constraint(
+        mut self,
+        response_constraint: Option<ResponseConstraint>,
+    ) -> Self {
+        self.response_constraint = response_constraint;
+        self
+    }
+
+    pub fn with_early_completion_margin(mut self, early_completion_margin: u8) -> Self {
+        self.early_completion_margin = early_completion_margin;
+        self
+    }
+
+    pub fn with_onchain_response(mut self, store_response_onchain: bool) -> Self {
+        self.store_response_onchain = store_response_onchain;
+        self
+    }
+
+    pub fn with_vote_weighting(mut self, vote_weighting: VoteWeighting) -> Self {
+        self.vote_weighting = vote_weighting;
+        self
+    }
+
+    pub fn with_challenge_period(mut self, challenge_period_seconds: i64) -> Self {
+        self.challenge_period_seconds = challenge_period_seconds;
+        self
+    }
+
+    pub fn with_rich_distribution(mut self, rich_distribution_callback: bool) -> Self {
+        self.rich_distribution_callback = rich_distribution_callback;
+        self
+    }
+
+    pub fn with_providers(mut self, providers: Vec<ProviderTarget>) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    pub fn with_multi_winner_policy(mut self, multi_winner_policy: MultiWinnerPolicy) -> Self {
+        self.multi_winner_policy = multi_winner_policy;
+        self
+    }
+
+    pub fn with_generation_params(
+        mut self,
+        generation_params: Option<GenerationParams>,
+    ) -> Self {
+        self.generation_params = generation_params;
+        self
+    }
+
+    pub fn with_voting_window(mut self, voting_window_seconds: i64) -> Self {
+        self.voting_window_seconds = voting_window_seconds;
+        self
+    }
+
+    /// Routes the callback to the consumer instruction `callback_ix_name`,
+    /// which must be 1-32 lowercase letters, digits or underscores.
+    pub fn with_callback_ix_name(mut self, callback_ix_name: String) -> Self {
+        self.callback_ix_name = callback_ix_name;
+        self
+    }
+
+    /// Sets the consensus `create_request_simple` sends. The values are
+    /// checked when the request is created, not here.
+    pub fn with_consensus(mut self, min_votes: u8, approval_threshold: u8) -> Self {
+        self.min_votes = min_votes;
+        self.approval_threshold = approval_threshold;
+        self
+    }
+
+    /// `create_request` with the consensus from `with_consensus`, or the
+    /// defaults when it was never called.
+    pub fn create_request_simple(
+        self,
+        request_id: String,
+        provider: String,
+        model_id: String,
+        messages: Vec<Message>,
+    ) -> Result<()> {
+        let (min_votes, approval_threshold) = (self.min_votes, self.approval_threshold);
+        self.create_request(request_id, provider, model_id, messages, min_votes, approval_threshold)
+    }
+
+    /// Fails with `InvalidArgument` before the CPI if `min_votes` or
+    /// `approval_threshold` is outside the range the router accepts.
     pub fn create_request(
         self,
         request_id: String,
@@ -52,20 +620,61 @@ impl<'info> CoolRouterCPI<'info> {
         min_votes: u8,
         approval_threshold: u8,
     ) -> Result<()> {
-        let data = Self::serialize_create_request(
-            &request_id,
-            &provider,
-            &model_id,
-            &messages,
+        validate_consensus(min_votes, approval_threshold)?;
+
+        let params = CreateRequestParams {
+            provider,
+            model_id,
+            messages,
             min_votes,
             approval_threshold,
-        )?;
+            tie_break: self.tie_break,
+            require_content_entropy: self.require_content_entropy,
+            approval_kind: self.approval_kind,
+            require_attestation: self.require_attestation,
+            reward_lamports: self.reward_lamports,
+            fulfiller_bonus_bps: self.fulfiller_bonus_bps,
+            consumer_cooldown_seconds: self.consumer_cooldown_seconds,
+            expiry_seconds: self.expiry_seconds,
+            normalize: self.normalize,
+            response_kind: self.response_kind,
+            notify_only: self.notify_only,
+            reward_mode: self.reward_mode,
+            encrypt_to: self.encrypt_to,
+            min_participation: self.min_participation,
+            fallback_model_id: self.fallback_model_id,
+            fee_per_response_byte: self.fee_per_response_byte,
+            trusted_oracle: self.trusted_oracle,
+            keeper_reward_lamports: self.keeper_reward_lamports,
+            pow_difficulty: self.pow_difficulty,
+            expires_at_slot: self.expires_at_slot,
+            sanitize_content: self.sanitize_content,
+            allow_cache: self.allow_cache,
+            cache_ttl_seconds: self.cache_ttl_seconds,
+            allowed_oracles: self.allowed_oracles,
+            detect_format: self.detect_format,
+            threshold_rounding: self.threshold_rounding,
+            response_constraint: self.response_constraint,
+            early_completion_margin: self.early_completion_margin,
+            store_response_onchain: self.store_response_onchain,
+            vote_weighting: self.vote_weighting,
+            challenge_period_seconds: self.challenge_period_seconds,
+            rich_distribution_callback: self.rich_distribution_callback,
+            providers: self.providers,
+            multi_winner_policy: self.multi_winner_policy,
+            generation_params: self.generation_params,
+            voting_window_seconds: self.voting_window_seconds,
+            callback_ix_name: self.callback_ix_name,
+        };
+        let data = Self::serialize_create_request(&request_id, &params)?;
 
         let cpi_accounts = vec![
-            self.request_pda.clone(),
             self.authority.clone(),
+            self.request_pda.clone(),
             self.caller_program.clone(),
             self.system_program.clone(),
+            self.config.clone(),
+            self.event_counter.clone(),
         ];
 
         let mut account_metas = cpi_accounts
@@ -85,6 +694,14 @@ impl<'info> CoolRouterCPI<'info> {
             });
         }
 
+        if let Some(cache) = &self.cache_account {
+            account_metas.push(AccountMeta::new_readonly(*cache.key, false));
+        }
+
+        if let Some(registry) = &self.registry_account {
+            account_metas.push(AccountMeta::new_readonly(*registry.key, false));
+        }
+
         let ix = anchor_lang::solana_program::instruction::Instruction {
             program_id: self.coolrouter_program,
             accounts: account_metas,
@@ -93,6 +710,8 @@ impl<'info> CoolRouterCPI<'info> {
 
         let mut all_accounts = cpi_accounts;
         all_accounts.extend(self.callback_accounts);
+        all_accounts.extend(self.cache_account);
+        all_accounts.extend(self.registry_account);
 
         anchor_lang::solana_program::program::invoke(&ix, &all_accounts)?;
 
@@ -101,11 +720,7 @@ impl<'info> CoolRouterCPI<'info> {
 
     fn serialize_create_request(
         request_id: &str,
-        provider: &str,
-        model_id: &str,
-        messages: &[Message],
-        min_votes: u8,
-        approval_threshold: u8,
+        params: &CreateRequestParams,
     ) -> Result<Vec<u8>> {
         let mut data = Vec::new();
 
@@ -113,11 +728,7 @@ impl<'info> CoolRouterCPI<'info> {
         data.extend_from_slice(&discriminator);
 
         data.extend_from_slice(&request_id.to_string().try_to_vec()?);
-        data.extend_from_slice(&provider.to_string().try_to_vec()?);
-        data.extend_from_slice(&model_id.to_string().try_to_vec()?);
-        data.extend_from_slice(&messages.to_vec().try_to_vec()?);
-        data.extend_from_slice(&min_votes.try_to_vec()?);
-        data.extend_from_slice(&approval_threshold.try_to_vec()?);
+        data.extend_from_slice(&params.try_to_vec()?);
 
         Ok(data)
     }
@@ -131,11 +742,28 @@ impl<'info> CoolRouterCPI<'info> {
     }
 }
 
+/// Mirrors the router's `InvalidMinVotes` and `InvalidApprovalThreshold`
+/// checks: at least one vote, and a threshold between 1 and 100 percent.
+pub fn validate_consensus(min_votes: u8, approval_threshold: u8) -> Result<()> {
+    if min_votes == 0 {
+        msg!("min_votes must be greater than 0");
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    if approval_threshold == 0 || approval_threshold > 100 {
+        msg!("approval_threshold must be between 1 and 100");
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn create_llm_request<'info>(
     request_pda: AccountInfo<'info>,
     authority: AccountInfo<'info>,
     caller_program: AccountInfo<'info>,
     system_program: AccountInfo<'info>,
+    config: AccountInfo<'info>,
+    event_counter: AccountInfo<'info>,
     coolrouter_program: Pubkey,
     callback_accounts: Vec<AccountInfo<'info>>,
     request_id: String,
@@ -150,8 +778,128 @@ pub fn create_llm_request<'info>(
         authority,
         caller_program,
         system_program,
+        config,
+        event_counter,
         coolrouter_program,
     )
     .add_callback_accounts(callback_accounts)
     .create_request(request_id, provider, model_id, messages, min_votes, approval_threshold)
-}
\ No newline at end of file
+}
+
+/// Acknowledges delivery of a fulfilled request. `acknowledger` must be the
+/// request payer or the caller program's PDA at `[b"delivery_ack", request_id]`,
+/// in which case `signer_seeds` are its seeds.
+pub fn acknowledge_delivery<'info>(
+    request_pda: AccountInfo<'info>,
+    acknowledger: AccountInfo<'info>,
+    coolrouter_program: Pubkey,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let ix = anchor_lang::solana_program::instruction::Instruction {
+        program_id: coolrouter_program,
+        accounts: vec![
+            AccountMeta::new(*request_pda.key, false),
+            AccountMeta::new_readonly(*acknowledger.key, true),
+        ],
+        data: CoolRouterCPI::calculate_discriminator("global:acknowledge_delivery").to_vec(),
+    };
+
+    anchor_lang::solana_program::program::invoke_signed(
+        &ix,
+        &[request_pda, acknowledger],
+        signer_seeds,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_messages_bytes_v1_is_stable() {
+        let messages = vec![
+            Message {
+                role: "system".to_string(),
+                content: "Be brief.".to_string(),
+            },
+            Message {
+                role: "user".to_string(),
+                content: "Hi".to_string(),
+            },
+        ];
+
+        let mut expected = vec![1, 2, 0, 0, 0];
+        expected.extend_from_slice(&[6, 0, 0, 0]);
+        expected.extend_from_slice(b"system");
+        expected.extend_from_slice(&[9, 0, 0, 0]);
+        expected.extend_from_slice(b"Be brief.");
+        expected.extend_from_slice(&[4, 0, 0, 0]);
+        expected.extend_from_slice(b"user");
+        expected.extend_from_slice(&[2, 0, 0, 0]);
+        expected.extend_from_slice(b"Hi");
+
+        assert_eq!(canonical_messages_bytes(&messages), expected);
+        assert_eq!(canonical_messages_bytes(&[]), vec![1, 0, 0, 0, 0]);
+    }
+
+    fn meta(model_id: &str, finish_reason: &str) -> ResponseMeta {
+        ResponseMeta {
+            model_id: model_id.to_string(),
+            finish_reason: finish_reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn canonical_response_bytes_match_borsh_tuple() {
+        let metadata = meta("gpt-4o", "stop");
+        let bytes = canonical_response_bytes("req-1", b"42", &metadata);
+
+        let borsh = ("req-1".to_string(), b"42".to_vec(), metadata).try_to_vec().unwrap();
+        assert_eq!(bytes, borsh);
+    }
+
+    #[test]
+    fn identical_responses_agree_despite_surrounding_data() {
+        // Fulfill arguments from two oracles that agree on the response and
+        // metadata but differ in the content type and attestation they send.
+        type FulfillArgs = (Vec<u8>, ResponseMeta, u8, Option<[u8; 64]>);
+        let first: FulfillArgs = (b"42".to_vec(), meta("gpt-4o", "stop"), CONTENT_TYPE_TEXT, None);
+        let second: FulfillArgs = (b"42".to_vec(), meta("gpt-4o", "stop"), CONTENT_TYPE_JSON, Some([7; 64]));
+
+        let preimage = |args: &FulfillArgs| {
+            let (response, metadata, _, _) =
+                FulfillArgs::try_from_slice(&args.try_to_vec().unwrap()).unwrap();
+            canonical_response_bytes("req-1", &response, &metadata)
+        };
+        assert_ne!(first.try_to_vec().unwrap(), second.try_to_vec().unwrap());
+        assert_eq!(preimage(&first), preimage(&second));
+    }
+
+    #[test]
+    fn field_boundaries_cannot_be_shifted() {
+        assert_ne!(
+            canonical_response_bytes("req-1", b"42", &meta("gpt-4o", "stop")),
+            canonical_response_bytes("req-14", b"2", &meta("gpt-4o", "stop")),
+        );
+        assert_ne!(
+            canonical_response_bytes("req-1", b"42", &meta("gpt-4o", "stop")),
+            canonical_response_bytes("req-1", b"42", &meta("gpt-4", "ostop")),
+        );
+        assert_ne!(
+            canonical_response_bytes("req-1", b"42", &meta("gpt-4o", "stop")),
+            canonical_response_bytes("req-1", b"42", &meta("gpt-4o", "length")),
+        );
+    }
+
+    #[test]
+    fn validate_consensus_matches_router_ranges() {
+        assert!(validate_consensus(DEFAULT_MIN_VOTES, DEFAULT_APPROVAL_THRESHOLD).is_ok());
+        assert!(validate_consensus(1, 1).is_ok());
+        assert!(validate_consensus(1, 100).is_ok());
+        assert!(validate_consensus(0, 66).is_err());
+        assert!(validate_consensus(3, 0).is_err());
+        assert!(validate_consensus(3, 101).is_err());
+    }
+}
@@ -1,11 +1,70 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
 use anchor_lang::solana_program::{instruction::Instruction, program::invoke};
-use solana_program::hash::hash;
+use solana_program::hash::{hash, hashv};
 
 declare_id!("CATsZNcHms98EcQo1qzGcA3XLPf47NLhQC5g2cRe19Gu");
 
 const MAX_CALLBACK_ACCOUNTS: usize = 32;
+const MAX_MODEL_ID_LEN: usize = 64;
+const MAX_CALLBACK_IX_NAME_LEN: usize = 32;
+const MAX_FINISH_REASON_LEN: usize = 32;
+const RESPONSE_META_SPACE: usize = (4 + MAX_MODEL_ID_LEN) + (4 + MAX_FINISH_REASON_LEN);
 const MAX_ORACLES: usize = 32;
+// Vote counters are u16; this keeps `votes_for` lossless if the cap is raised.
+const _: () = assert!(MAX_ORACLES <= u16::MAX as usize);
+const MAX_BATCH_FULFILL: usize = 4;
+const MAX_ORACLE_BATCH: usize = 16;
+const MAX_DECLINED: usize = 32;
+const MAX_PROVIDERS: usize = 4;
+const MAX_POW_DIFFICULTY: u8 = 32;
+const MAX_FORBIDDEN_SUBSTRINGS: usize = 8;
+const MAX_FORBIDDEN_SUBSTRING_LEN: usize = 32;
+const MAX_BLACKLIST: usize = 32;
+const MAX_PROVIDER_STATUSES: usize = 8;
+const PROVIDER_DEGRADED_QUORUM: usize = 3;
+const PROVIDER_STATUS_TTL_SECONDS: i64 = 3_600;
+const MAX_REGISTERED_ORACLES: usize = 64;
+const MAX_CACHED_RESPONSE_LEN: usize = 1024;
+const MAX_STATE_LOG: usize = 8;
+const MAX_CONSTRAINT_OPTIONS: usize = 8;
+const MAX_CONSTRAINT_OPTION_LEN: usize = 32;
+/// Largest response stored on the request account; a stored response must fit
+/// in the 1024-byte return data of `get_stored_response` with its length prefix.
+const MAX_STORED_RESPONSE: usize = 1020;
+const MAX_CHUNKED_RESPONSE_LEN: usize = 8192;
+const MAX_MESSAGE_CONTENT_LEN: usize = 1024;
+const MAX_TEMPERATURE: u16 = 2_000;
+const MAX_TOP_P: u16 = 1_000;
+const MESSAGE_ROLES: [&str; 3] = ["system", "user", "assistant"];
+const MIN_DISTINCT_RESPONSE_BYTES: usize = 2;
+const MAX_DOMINANT_BYTE_PERCENT: usize = 95;
+const ED25519_HEADER_LEN: usize = 16;
+const ED25519_PROGRAM_ID: Pubkey = pubkey!("Ed25519SigVerify111111111111111111111111111");
+const MAX_BPS: u16 = 10_000;
+const MAX_EVENT_LOG_LEN: usize = 10_000;
+const MIN_CLUSTER_VOTES: u16 = 2;
+const MINORITY_REWARD_WEIGHT_BPS: u16 = 5_000;
+const SECONDS_PER_DAY: i64 = 86_400;
+/// How long after fulfillment unclaimed rewards keep the request from closing.
+const REWARD_CLAIM_WINDOW_SECONDS: i64 = 7 * SECONDS_PER_DAY;
+
+const FORMAT_UNDETECTED: u8 = 0;
+const FORMAT_TEXT: u8 = 1;
+const FORMAT_JSON: u8 = 2;
+const FORMAT_BINARY: u8 = 3;
+
+const CONTENT_TYPE_TEXT: u8 = 0;
+const CONTENT_TYPE_JSON: u8 = 1;
+const CONTENT_TYPE_BINARY: u8 = 2;
+
+const INVARIANT_CALLBACK_LENGTHS: u8 = 1 << 0;
+const INVARIANT_VOTE_COUNT: u8 = 1 << 1;
+const INVARIANT_WINNING_HASH: u8 = 1 << 2;
+const INVARIANT_DUPLICATE_ORACLE: u8 = 1 << 3;
 
 #[program]
 pub mod coolrouter {
@@ -14,61 +73,361 @@ pub mod coolrouter {
     pub fn create_request<'info>(
         ctx: Context<'_, '_, '_, 'info, CreateRequest<'info>>,
         request_id: String,
-        provider: String,
-        model_id: String,
-        messages: Vec<Message>,
-        min_votes: u8,
-        approval_threshold: u8,
+        params: CreateRequestParams,
     ) -> Result<()> {
+        let CreateRequestParams {
+            provider,
+            model_id,
+            messages,
+            min_votes,
+            approval_threshold,
+            tie_break,
+            require_content_entropy,
+            approval_kind,
+            require_attestation,
+            reward_lamports,
+            fulfiller_bonus_bps,
+            consumer_cooldown_seconds,
+            expiry_seconds,
+            normalize,
+            response_kind,
+            notify_only,
+            reward_mode,
+            encrypt_to,
+            min_participation,
+            fallback_model_id,
+            fee_per_response_byte,
+            trusted_oracle,
+            keeper_reward_lamports,
+            pow_difficulty,
+            expires_at_slot,
+            sanitize_content,
+            allow_cache,
+            cache_ttl_seconds,
+            allowed_oracles,
+            detect_format,
+            threshold_rounding,
+            response_constraint,
+            early_completion_margin,
+            store_response_onchain,
+            vote_weighting,
+            challenge_period_seconds,
+            rich_distribution_callback,
+            providers,
+            multi_winner_policy,
+            generation_params,
+            voting_window_seconds,
+            callback_ix_name,
+        } = params;
+        
         let request = &mut ctx.accounts.request;
         let clock = Clock::get()?;
         
         require!(provider.len() <= 64, ErrorCode::ProviderTooLong);
-        require!(model_id.len() <= 64, ErrorCode::ModelIdTooLong);
+        require!(model_id.len() <= MAX_MODEL_ID_LEN, ErrorCode::ModelIdTooLong);
+        require!(
+            fallback_model_id
+                .as_ref()
+                .is_none_or(|fallback| fallback.len() <= MAX_MODEL_ID_LEN),
+            ErrorCode::ModelIdTooLong
+        );
+        require!(providers.len() <= MAX_PROVIDERS, ErrorCode::TooManyProviders);
+        for target in &providers {
+            require!(target.provider.len() <= 64, ErrorCode::ProviderTooLong);
+            require!(target.model_id.len() <= MAX_MODEL_ID_LEN, ErrorCode::ModelIdTooLong);
+        }
         require!(messages.len() <= 50, ErrorCode::TooManyMessages);
+        validate_messages(&messages)?;
+        require!(
+            generation_params.as_ref().is_none_or(is_valid_generation_params),
+            ErrorCode::InvalidGenerationParams
+        );
+        
+        let config = load_config(&ctx.accounts.config)?;
+        ensure_not_paused(config.as_ref())?;
+        require!(
+            !config.as_ref().is_some_and(|config| {
+                provider_degraded(&config.provider_statuses, &provider, clock.unix_timestamp)
+            }),
+            ErrorCode::ProviderUnavailable
+        );
+        
+        let remaining_accounts = if let Some(trusted_oracle) = &trusted_oracle {
+            let (registry_info, remaining_accounts) = ctx
+                .remaining_accounts
+                .split_last()
+                .ok_or(ErrorCode::MissingRegistryAccount)?;
+            let registry = load_registry(registry_info)?;
+            require!(
+                is_active_oracle(&registry, trusted_oracle),
+                ErrorCode::TrustedOracleNotRegistered
+            );
+            remaining_accounts
+        } else {
+            ctx.remaining_accounts
+        };
+        
+        let (callback_infos, cache_info) = if allow_cache {
+            require!(cache_ttl_seconds >= 0, ErrorCode::InvalidCacheTtl);
+            let (cache_info, callback_infos) = remaining_accounts
+                .split_last()
+                .ok_or(ErrorCode::MissingCacheAccount)?;
+            (callback_infos, Some(cache_info))
+        } else {
+            (remaining_accounts, None)
+        };
+        
         require!(
-            ctx.remaining_accounts.len() <= MAX_CALLBACK_ACCOUNTS,
+            callback_infos.len() <= MAX_CALLBACK_ACCOUNTS,
             ErrorCode::TooManyAccounts
         );
+        
+        if sanitize_content {
+            let config = config.as_ref().ok_or(ErrorCode::MissingConfigAccount)?;
+            
+            if let Some(forbidden) = find_forbidden_substring(&messages, &config.forbidden_substrings) {
+                msg!("Message content contains forbidden substring: {}", forbidden);
+                return err!(ErrorCode::SuspiciousContent);
+            }
+        }
+        
+        require!(
+            encrypt_to.is_none() || normalize == NormalizeMode::Raw,
+            ErrorCode::NormalizeEncryptedResponse
+        );
+        require!(
+            encrypt_to.is_none() || response_constraint.is_none(),
+            ErrorCode::InvalidResponseConstraint
+        );
+        require!(
+            response_constraint.as_ref().is_none_or(is_valid_constraint),
+            ErrorCode::InvalidResponseConstraint
+        );
         require!(min_votes > 0, ErrorCode::InvalidMinVotes);
+        require!(
+            min_participation as usize <= MAX_ORACLES,
+            ErrorCode::InvalidMinParticipation
+        );
+        require!(
+            allowed_oracles
+                .as_ref()
+                .is_none_or(|allowed| allowed.len() <= MAX_ORACLES),
+            ErrorCode::TooManyAllowedOracles
+        );
         require!(
             approval_threshold > 0 && approval_threshold <= 100,
             ErrorCode::InvalidApprovalThreshold
         );
+        require!(
+            fulfiller_bonus_bps <= MAX_BPS,
+            ErrorCode::InvalidRewardSplit
+        );
+        require!(
+            consumer_cooldown_seconds >= 0,
+            ErrorCode::InvalidCooldown
+        );
+        require!(expiry_seconds >= 0, ErrorCode::InvalidExpiry);
+        require!(
+            challenge_period_seconds >= 0,
+            ErrorCode::InvalidChallengePeriod
+        );
+        require!(voting_window_seconds >= 0, ErrorCode::InvalidVotingWindow);
+        require!(
+            is_valid_callback_ix_name(&callback_ix_name),
+            ErrorCode::InvalidCallbackIxName
+        );
+        require!(
+            expiry_seconds == 0 || expires_at_slot == 0,
+            ErrorCode::ConflictingExpiry
+        );
+        require!(
+            expires_at_slot == 0 || expires_at_slot > clock.slot,
+            ErrorCode::InvalidExpiry
+        );
+        require!(
+            keeper_reward_lamports <= reward_lamports,
+            ErrorCode::InvalidKeeperReward
+        );
+        require!(
+            pow_difficulty <= MAX_POW_DIFFICULTY,
+            ErrorCode::InvalidPowDifficulty
+        );
         
         let callback_program = *ctx.accounts.caller_program.key;
         
+        validate_callback_program(&callback_program)?;
+        
         let mut callback_accounts = Vec::new();
         let mut callback_writable = Vec::new();
         
-        for account in ctx.remaining_accounts {
+        for account in callback_infos {
             callback_accounts.push(*account.key);
             callback_writable.push(account.is_writable);
         }
         
         request.id = request_id.clone();
         request.caller_program = callback_program;
+        request.payer = ctx.accounts.payer.key();
         request.provider = provider.clone();
         request.model_id = model_id.clone();
         request.callback_accounts = callback_accounts;
         request.callback_writable = callback_writable;
-        request.status = RequestStatus::Pending;
+        request.state_log = Vec::new();
+        transition(request, RequestStatus::Pending, clock.unix_timestamp);
         request.created_at = clock.unix_timestamp;
         request.min_votes = min_votes;
         request.approval_threshold = approval_threshold;
         request.votes = Vec::new();
+        request.vote_epoch = 0;
         request.winning_hash = None;
+        request.winning_hash_locked = false;
         request.total_votes_cast = 0;
+        request.tie_break = tie_break.clone();
+        request.callback_program_override = None;
+        request.require_content_entropy = require_content_entropy;
+        request.approval_kind = approval_kind.clone();
+        request.require_attestation = require_attestation;
+        request.reward_lamports = reward_lamports;
+        request.fulfiller_bonus_bps = fulfiller_bonus_bps;
+        request.fulfilled_by = None;
+        let canonical_messages: Vec<coolrouter_cpi::Message> = messages
+            .iter()
+            .map(|message| coolrouter_cpi::Message {
+                role: message.role.clone(),
+                content: message.content.clone(),
+            })
+            .collect();
+        request.conversation_hash =
+            hash(&coolrouter_cpi::canonical_messages_bytes(&canonical_messages)).to_bytes();
+        request.consumer_cooldown_seconds = consumer_cooldown_seconds;
+        request.paused = false;
+        request.normalize = normalize;
+        request.response_kind = response_kind.clone();
+        request.notify_only = notify_only;
+        request.reward_mode = reward_mode;
+        request.peak_vote_count = 0;
+        request.encrypt_to = encrypt_to;
+        request.fallback_model_id = fallback_model_id;
+        request.fee_per_response_byte = fee_per_response_byte;
+        request.declined = Vec::new();
+        request.trusted_oracle = trusted_oracle;
+        request.keeper_reward_lamports = keeper_reward_lamports;
+        request.pow_difficulty = pow_difficulty;
+        request.used_fallback = false;
+        request.delivery_acknowledged = false;
+        request.acknowledged_at = None;
+        request.min_participation = if min_participation == 0 {
+            min_votes
+        } else {
+            min_participation
+        };
+        request.expires_at = if expiry_seconds > 0 {
+            Some(clock.unix_timestamp + expiry_seconds)
+        } else {
+            None
+        };
+        request.created_slot = clock.slot;
+        request.voting_completed_at = None;
+        request.fulfilled_at = None;
+        request.expires_at_slot = if expires_at_slot > 0 {
+            Some(expires_at_slot)
+        } else {
+            None
+        };
+        request.allow_cache = allow_cache;
+        request.allowed_oracles = allowed_oracles;
+        request.detect_format = detect_format;
+        request.detected_format = FORMAT_UNDETECTED;
+        request.threshold_rounding = threshold_rounding;
+        request.response_constraint = response_constraint;
+        request.early_completion_margin = early_completion_margin;
+        request.store_response_onchain = store_response_onchain;
+        request.stored_response = None;
+        request.vote_weighting = vote_weighting;
+        request.challenge_period_seconds = challenge_period_seconds;
+        request.challenger = None;
+        request.rich_distribution_callback = rich_distribution_callback;
+        request.providers = providers.clone();
+        request.multi_winner_policy = multi_winner_policy;
+        request.generation_params = generation_params.clone();
+        request.voting_window_seconds = voting_window_seconds;
+        request.voting_deadline = None;
+        request.callback_ix_name = callback_ix_name;
+        request.cache_key = result_cache_key(request)?;
+        
+        if let Some(cache_info) = cache_info {
+            let cached = load_fresh_cache(
+                cache_info,
+                &request.cache_key,
+                &request.model_id,
+                cache_ttl_seconds,
+                clock.unix_timestamp,
+            )?;
+            
+            if let Some(cached) = cached {
+                let winning_hash = response_hash(request, &cached.response, &cached.metadata);
+                set_winning_hash(request, Some(winning_hash))?;
+                transition(request, RequestStatus::VotingCompleted, clock.unix_timestamp);
+                request.voting_completed_at = Some(clock.unix_timestamp);
+                
+                emit!(CacheHit {
+                    request_id: request_id.clone(),
+                    conversation_hash: request.conversation_hash,
+                    winning_hash,
+                    cached_at: cached.cached_at,
+                });
+                
+                msg!("Request completed from cache: {}", request_id);
+            }
+        }
         
-        emit!(RequestCreated {
+        let event = RequestCreated {
+            seq: next_event_seq(&mut ctx.accounts.event_counter)?,
             request_id: request_id.clone(),
             caller_program: callback_program,
-            provider: provider,
-            model_id: model_id,
-            messages: messages,
-            min_votes: min_votes,
-            approval_threshold: approval_threshold,
-        });
+            provider,
+            model_id,
+            messages,
+            min_votes,
+            approval_threshold,
+            tie_break,
+            approval_kind,
+            reward_lamports,
+            conversation_hash: request.conversation_hash,
+            response_kind,
+            encrypt_to,
+            allowed_oracles_len: request
+                .allowed_oracles
+                .as_ref()
+                .map(|allowed| allowed.len() as u8),
+            providers,
+            generation_params,
+        };
+        
+        let event_len = encoded_event_len(&event);
+        if event_len > MAX_EVENT_LOG_LEN {
+            msg!(
+                "RequestCreated event is {} bytes encoded, limit is {}",
+                event_len,
+                MAX_EVENT_LOG_LEN
+            );
+            return err!(ErrorCode::EventTooLarge);
+        }
+        
+        emit!(event);
+        
+        if reward_lamports > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: ctx.accounts.request.to_account_info(),
+                    },
+                ),
+                reward_lamports,
+            )?;
+        }
         
         msg!("Request created: {}", request_id);
         
@@ -78,8 +437,112 @@ pub mod coolrouter {
     pub fn submit_vote(
         ctx: Context<SubmitVote>,
         response_hash: [u8; 32],
+        nonce: u64,
+        provider_index: u8,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        
+        ensure_vote_open(
+            &ctx.accounts.request,
+            &ctx.accounts.config,
+            &response_hash,
+            nonce,
+            provider_index,
+            &clock,
+        )?;
+        
+        let mut accounts = ctx.accounts.vote_accounts();
+        
+        authorize_vote(&accounts, clock.unix_timestamp)?;
+        
+        cast_vote(&mut accounts, response_hash, provider_index, clock.unix_timestamp)
+    }
+
+    pub fn submit_vote_monitored(
+        ctx: Context<SubmitVote>,
+        response_hash: [u8; 32],
+        nonce: u64,
+        provider_index: u8,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        
+        ensure_vote_open(
+            &ctx.accounts.request,
+            &ctx.accounts.config,
+            &response_hash,
+            nonce,
+            provider_index,
+            &clock,
+        )?;
+        
+        let mut accounts = ctx.accounts.vote_accounts();
+        
+        if let Err(error) = authorize_vote(&accounts, clock.unix_timestamp) {
+            let oracle_key = accounts.oracle;
+            
+            emit!(UnauthorizedVoteAttempt {
+                oracle: oracle_key,
+                request_id: accounts.request.id.clone(),
+                reason: rejection_reason(error),
+            });
+            
+            msg!("Vote rejected for oracle: {}", oracle_key);
+            
+            return Ok(());
+        }
+        
+        cast_vote(&mut accounts, response_hash, provider_index, clock.unix_timestamp)
+    }
+
+    /// Records a vote signed off-chain by `oracle` and submitted by a relayer
+    /// that pays the fees. The preceding instruction must be an ed25519
+    /// verification of `relayed_vote_message` by the oracle's key.
+    pub fn submit_vote_relayed(
+        ctx: Context<SubmitVoteRelayed>,
+        oracle: Pubkey,
+        response_hash: [u8; 32],
+        nonce: u64,
+        provider_index: u8,
     ) -> Result<()> {
+        let clock = Clock::get()?;
+        
+        ensure_vote_open(
+            &ctx.accounts.request,
+            &ctx.accounts.config,
+            &response_hash,
+            nonce,
+            provider_index,
+            &clock,
+        )?;
+        
+        let message = relayed_vote_message(
+            &ctx.accounts.request.key(),
+            ctx.accounts.request.vote_epoch,
+            &response_hash,
+            nonce,
+            provider_index,
+        );
+        verify_attestation(&ctx.accounts.instructions_sysvar, &oracle, &message)?;
+        
+        let relayer = ctx.accounts.relayer.key();
+        let mut accounts = ctx.accounts.vote_accounts(oracle);
+        
+        authorize_vote(&accounts, clock.unix_timestamp)?;
+        
+        cast_vote(&mut accounts, response_hash, provider_index, clock.unix_timestamp)?;
+        
+        emit!(RelayedVoteSubmitted {
+            request_id: accounts.request.id.clone(),
+            oracle,
+            relayer,
+        });
+        
+        Ok(())
+    }
+
+    pub fn decline_request(ctx: Context<DeclineRequest>) -> Result<()> {
         let request = &mut ctx.accounts.request;
+        let oracle_key = ctx.accounts.oracle.key();
         
         require!(
             request.status == RequestStatus::Pending,
@@ -87,280 +550,5436 @@ pub mod coolrouter {
         );
         
         require!(
-            request.votes.len() < MAX_ORACLES,
-            ErrorCode::TooManyVotes
+            !request.votes.iter().any(|vote| vote.oracle == oracle_key),
+            ErrorCode::OracleAlreadyVoted
         );
         
-        let oracle_key = ctx.accounts.oracle.key();
+        require!(
+            !request.declined.contains(&oracle_key),
+            ErrorCode::OracleAlreadyDeclined
+        );
         
-        for vote in &request.votes {
-            require!(
-                vote.oracle != oracle_key,
-                ErrorCode::OracleAlreadyVoted
-            );
-        }
+        require!(
+            request.declined.len() < MAX_DECLINED,
+            ErrorCode::TooManyDeclines
+        );
+        
+        request.declined.push(oracle_key);
         
-        request.votes.push(OracleVote {
+        emit!(RequestDeclined {
+            request_id: request.id.clone(),
             oracle: oracle_key,
-            response_hash,
         });
-        request.total_votes_cast += 1;
-        
-        let vote_results = count_votes(&request.votes);
-        
-        if let Some((winning_hash, vote_count)) = vote_results.iter().max_by_key(|(_, count)| *count) {
-            let total_votes = request.total_votes_cast as u64;
-            let vote_percentage = ((*vote_count as u64) * 100) / total_votes;
-            
-            if *vote_count >= request.min_votes && vote_percentage >= request.approval_threshold as u64 {
-                request.winning_hash = Some(*winning_hash);
-                request.status = RequestStatus::VotingCompleted;
-                
-                emit!(VotingCompleted {
-                    request_id: request.id.clone(),
-                    winning_hash: *winning_hash,
-                    vote_count: *vote_count,
-                    total_votes: request.total_votes_cast,
-                });
-                
-                msg!("Voting completed for request: {}", request.id);
-            }
-        }
         
-        msg!("Vote submitted by oracle: {}", oracle_key);
+        msg!("Request declined by oracle: {}", oracle_key);
         
         Ok(())
     }
 
-    pub fn fulfill_request<'info>(
-        ctx: Context<'_, '_, '_, 'info, FulfillRequest<'info>>,
-        response: Vec<u8>,
-    ) -> Result<()> {
+    pub fn raise_dispute(ctx: Context<RaiseDispute>) -> Result<()> {
         let request = &mut ctx.accounts.request;
+        let challenger = ctx.accounts.oracle.key();
+        let now = Clock::get()?.unix_timestamp;
         
         require!(
             request.status == RequestStatus::VotingCompleted,
             ErrorCode::VotingNotCompleted
         );
         
-        let winning_hash = request.winning_hash.ok_or(ErrorCode::NoWinningHash)?;
-        
-        let response_hash = hash(&response).to_bytes();
         require!(
-            response_hash == winning_hash,
-            ErrorCode::ResponseHashMismatch
+            challenge_window_open(
+                request.challenge_period_seconds,
+                request.voting_completed_at,
+                now,
+            ),
+            ErrorCode::ChallengeWindowClosed
         );
         
         require!(
-            ctx.accounts.callback_program.key() == request.caller_program,
-            ErrorCode::CallbackProgramMismatch
+            is_active_oracle(&ctx.accounts.registry, &challenger),
+            ErrorCode::OracleNotRegistered
+        );
+        
+        request.challenger = Some(challenger);
+        transition(request, RequestStatus::Disputed, now);
+        
+        emit!(DisputeRaised {
+            request_id: request.id.clone(),
+            challenger,
+            winning_hash: request.winning_hash,
+        });
+        
+        msg!("Dispute raised by oracle: {}", challenger);
+        
+        Ok(())
+    }
+
+    pub fn init_oracle_cooldown(
+        ctx: Context<InitOracleCooldown>,
+        caller_program: Pubkey,
+    ) -> Result<()> {
+        let cooldown = &mut ctx.accounts.cooldown;
+        
+        cooldown.oracle = ctx.accounts.oracle.key();
+        cooldown.caller_program = caller_program;
+        cooldown.last_vote_at = 0;
+        cooldown.bump = ctx.bumps.cooldown;
+        
+        Ok(())
+    }
+
+    pub fn initialize_registry(
+        ctx: Context<InitializeRegistry>,
+        min_stake: u64,
+        deregistration_cooldown_seconds: i64,
+    ) -> Result<()> {
+        require!(deregistration_cooldown_seconds >= 0, ErrorCode::InvalidCooldown);
+        
+        let registry = &mut ctx.accounts.registry;
+        
+        registry.authority = ctx.accounts.authority.key();
+        registry.min_stake = min_stake;
+        registry.deregistration_cooldown_seconds = deregistration_cooldown_seconds;
+        registry.oracles = Vec::new();
+        registry.bump = ctx.bumps.registry;
+        ctx.accounts.vault.bump = ctx.bumps.vault;
+        
+        msg!("Oracle registry initialized with min stake: {}", min_stake);
+        
+        Ok(())
+    }
+
+    pub fn set_min_stake(ctx: Context<UpdateRegistry>, min_stake: u64) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+        
+        require_keys_eq!(
+            registry.authority,
+            ctx.accounts.authority.key(),
+            ErrorCode::Unauthorized
         );
         
+        registry.min_stake = min_stake;
+        
+        emit!(MinStakeUpdated { min_stake });
+        
+        Ok(())
+    }
+
+    /// Admin onboarding without stake: batch-registered oracles hold zero
+    /// stake, so they pass the vote gate only while `min_stake` is zero.
+    pub fn register_oracles_batch(
+        ctx: Context<UpdateRegistry>,
+        oracles: Vec<Pubkey>,
+    ) -> Result<OracleBatchRegistration> {
+        let registry = &mut ctx.accounts.registry;
+        
+        require_keys_eq!(
+            registry.authority,
+            ctx.accounts.authority.key(),
+            ErrorCode::Unauthorized
+        );
         require!(
-            ctx.remaining_accounts.len() == request.callback_accounts.len(),
-            ErrorCode::AccountCountMismatch
+            !oracles.is_empty() && oracles.len() <= MAX_ORACLE_BATCH,
+            ErrorCode::InvalidBatchSize
         );
         
-        for (i, expected_key) in request.callback_accounts.iter().enumerate() {
+        let mut summary = OracleBatchRegistration { added: 0, skipped: 0 };
+        
+        for oracle in oracles {
+            if registry.oracles.iter().any(|entry| entry.oracle == oracle) {
+                summary.skipped += 1;
+                continue;
+            }
+            
             require!(
-                ctx.remaining_accounts[i].key() == *expected_key,
-                ErrorCode::AccountMismatch
+                registry.oracles.len() < MAX_REGISTERED_ORACLES,
+                ErrorCode::RegistryFull
             );
+            
+            registry.oracles.push(RegisteredOracle {
+                oracle,
+                stake: 0,
+                deregistering_at: None,
+            });
+            summary.added += 1;
         }
         
-        let discriminator: [u8; 8] = hash(b"global:llm_callback")
-            .to_bytes()[..8]
-            .try_into()
-            .unwrap();
+        emit!(OraclesBatchRegistered {
+            added: summary.added,
+            skipped: summary.skipped,
+        });
         
-        let mut callback_data: Vec<u8> = discriminator.to_vec();
-        callback_data.extend_from_slice(&(request.id.clone(), response.clone()).try_to_vec()?);
+        msg!(
+            "Batch registration: {} added, {} skipped",
+            summary.added,
+            summary.skipped
+        );
         
-        let mut account_metas = vec![];
-        for (i, pubkey) in request.callback_accounts.iter().enumerate() {
-            account_metas.push(AccountMeta {
-                pubkey: *pubkey,
-                is_signer: false,
-                is_writable: request.callback_writable[i],
-            });
-        }
+        Ok(summary)
+    }
+
+    pub fn register_oracle(ctx: Context<RegisterOracle>, stake: u64) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+        let oracle_key = ctx.accounts.oracle.key();
         
-        let ix = Instruction {
-            program_id: request.caller_program,
-            accounts: account_metas,
-            data: callback_data,
-        };
+        require!(
+            !registry.oracles.iter().any(|entry| entry.oracle == oracle_key),
+            ErrorCode::OracleAlreadyRegistered
+        );
+        require!(
+            registry.oracles.len() < MAX_REGISTERED_ORACLES,
+            ErrorCode::RegistryFull
+        );
+        require!(stake >= registry.min_stake, ErrorCode::InsufficientStake);
         
-        invoke(
-            &ix,
-            ctx.remaining_accounts,
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.oracle.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                },
+            ),
+            stake,
         )?;
         
-        request.status = RequestStatus::Fulfilled;
+        registry.oracles.push(RegisteredOracle {
+            oracle: oracle_key,
+            stake,
+            deregistering_at: None,
+        });
         
-        emit!(RequestFulfilled {
-            request_id: request.id.clone(),
-            response_length: response.len() as u64,
+        emit!(OracleRegistered {
+            oracle: oracle_key,
+            stake,
         });
         
-        msg!("Request fulfilled: {}", request.id);
+        msg!("Oracle registered: {}", oracle_key);
         
         Ok(())
     }
-}
 
-fn count_votes(votes: &[OracleVote]) -> Vec<([u8; 32], u8)> {
-    let mut hash_counts: Vec<([u8; 32], u8)> = Vec::new();
-    
-    for vote in votes {
-        let mut found = false;
-        for (hash, count) in hash_counts.iter_mut() {
-            if *hash == vote.response_hash {
-                *count += 1;
-                found = true;
-                break;
-            }
-        }
-        if !found {
-            hash_counts.push((vote.response_hash, 1));
-        }
+    pub fn deregister_oracle(ctx: Context<DeregisterOracle>) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+        let oracle_key = ctx.accounts.oracle.key();
+        let now = Clock::get()?.unix_timestamp;
+        
+        let entry = registry
+            .oracles
+            .iter_mut()
+            .find(|entry| entry.oracle == oracle_key)
+            .ok_or(ErrorCode::OracleNotRegistered)?;
+        require!(
+            entry.deregistering_at.is_none(),
+            ErrorCode::OracleAlreadyDeregistering
+        );
+        entry.deregistering_at = Some(now);
+        
+        emit!(OracleDeregistrationStarted {
+            oracle: oracle_key,
+            withdrawable_at: now + registry.deregistration_cooldown_seconds,
+        });
+        
+        msg!("Oracle deregistration started: {}", oracle_key);
+        
+        Ok(())
     }
-    
-    hash_counts
-}
-
-#[derive(Accounts)]
-#[instruction(request_id: String)]
-pub struct CreateRequest<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = 8 + 64 + 32 + 64 + 64 
-            + (4 + 32 * MAX_CALLBACK_ACCOUNTS) 
-            + (4 + 1 * MAX_CALLBACK_ACCOUNTS) 
-            + 1 + 8 + 1 + 1 
-            + (4 + 64 * MAX_ORACLES) 
-            + (1 + 32) 
-            + 1,
-        seeds = [b"request", request_id.as_bytes()],
-        bump
-    )]
-    pub request: Account<'info, LLMRequest>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    /// CHECK: The calling program
-    pub caller_program: AccountInfo<'info>,
-    pub system_program: Program<'info, System>,
-}
 
-#[derive(Accounts)]
-pub struct SubmitVote<'info> {
-    #[account(mut)]
-    pub request: Account<'info, LLMRequest>,
-    pub oracle: Signer<'info>,
-}
+    pub fn withdraw_stake(ctx: Context<DeregisterOracle>) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+        let oracle_key = ctx.accounts.oracle.key();
+        let now = Clock::get()?.unix_timestamp;
+        
+        let position = registry
+            .oracles
+            .iter()
+            .position(|entry| entry.oracle == oracle_key)
+            .ok_or(ErrorCode::OracleNotRegistered)?;
+        let deregistering_at = registry.oracles[position]
+            .deregistering_at
+            .ok_or(ErrorCode::OracleNotDeregistering)?;
+        require!(
+            now >= deregistering_at + registry.deregistration_cooldown_seconds,
+            ErrorCode::StakeCooldownActive
+        );
+        
+        let stake = registry.oracles.swap_remove(position).stake;
+        
+        **ctx.accounts.vault.to_account_info().try_borrow_mut_lamports()? -= stake;
+        **ctx.accounts.oracle.to_account_info().try_borrow_mut_lamports()? += stake;
+        
+        emit!(StakeWithdrawn {
+            oracle: oracle_key,
+            stake,
+        });
+        
+        msg!("Stake of {} lamports returned to oracle: {}", stake, oracle_key);
+        
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct FulfillRequest<'info> {
+    pub fn init_result_cache(ctx: Context<InitResultCache>, cache_key: [u8; 32]) -> Result<()> {
+        let cache = &mut ctx.accounts.cache;
+        
+        cache.cache_key = cache_key;
+        cache.model_id = String::new();
+        cache.response = Vec::new();
+        cache.cached_at = 0;
+        cache.bump = ctx.bumps.cache;
+        cache.metadata = ResponseMeta {
+            model_id: String::new(),
+            finish_reason: String::new(),
+        };
+        
+        Ok(())
+    }
+
+    pub fn init_oracle_account(ctx: Context<InitOracleAccount>) -> Result<()> {
+        let oracle_account = &mut ctx.accounts.oracle_account;
+        
+        oracle_account.oracle = ctx.accounts.oracle.key();
+        oracle_account.votes_today = 0;
+        oracle_account.day_bucket = 0;
+        oracle_account.bump = ctx.bumps.oracle_account;
+        
+        Ok(())
+    }
+
+    pub fn init_event_counter(ctx: Context<InitEventCounter>) -> Result<()> {
+        let event_counter = &mut ctx.accounts.event_counter;
+        
+        event_counter.value = 0;
+        event_counter.bump = ctx.bumps.event_counter;
+        
+        Ok(())
+    }
+
+    pub fn initialize_config(ctx: Context<InitializeConfig>, daily_vote_cap: u32) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        
+        config.admin = ctx.accounts.admin.key();
+        config.daily_vote_cap = daily_vote_cap;
+        config.forbidden_substrings = Vec::new();
+        config.treasury = config.admin;
+        config.protocol_fee_bps = 0;
+        config.blacklist = Vec::new();
+        config.paused = false;
+        config.provider_statuses = Vec::new();
+        config.bump = ctx.bumps.config;
+        
+        msg!("Global config initialized with admin: {}", config.admin);
+        
+        Ok(())
+    }
+
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        
+        require_keys_eq!(
+            config.admin,
+            ctx.accounts.admin.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        config.paused = paused;
+        
+        emit!(RouterPauseChanged { paused });
+        
+        msg!("Router paused: {}", paused);
+        
+        Ok(())
+    }
+
+    pub fn set_daily_vote_cap(ctx: Context<UpdateConfig>, daily_vote_cap: u32) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        
+        require_keys_eq!(
+            config.admin,
+            ctx.accounts.admin.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        config.daily_vote_cap = daily_vote_cap;
+        
+        emit!(DailyVoteCapUpdated { daily_vote_cap });
+        
+        Ok(())
+    }
+
+    pub fn set_forbidden_substrings(
+        ctx: Context<UpdateConfig>,
+        forbidden_substrings: Vec<String>,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        
+        require_keys_eq!(
+            config.admin,
+            ctx.accounts.admin.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(
+            forbidden_substrings.len() <= MAX_FORBIDDEN_SUBSTRINGS
+                && forbidden_substrings.iter().all(|substring| {
+                    !substring.is_empty() && substring.len() <= MAX_FORBIDDEN_SUBSTRING_LEN
+                }),
+            ErrorCode::InvalidForbiddenSubstrings
+        );
+        
+        config.forbidden_substrings = forbidden_substrings;
+        
+        msg!("Forbidden substrings updated: {}", config.forbidden_substrings.len());
+        
+        Ok(())
+    }
+
+    pub fn set_protocol_fee(
+        ctx: Context<UpdateConfig>,
+        treasury: Pubkey,
+        protocol_fee_bps: u16,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        
+        require_keys_eq!(
+            config.admin,
+            ctx.accounts.admin.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(protocol_fee_bps <= MAX_BPS, ErrorCode::InvalidProtocolFee);
+        
+        config.treasury = treasury;
+        config.protocol_fee_bps = protocol_fee_bps;
+        
+        msg!("Protocol fee set to {} bps for treasury: {}", protocol_fee_bps, treasury);
+        
+        Ok(())
+    }
+
+    pub fn blacklist_oracle(ctx: Context<UpdateConfig>, oracle: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        
+        require_keys_eq!(
+            config.admin,
+            ctx.accounts.admin.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(
+            !config.blacklist.contains(&oracle),
+            ErrorCode::OracleBlacklisted
+        );
+        require!(
+            config.blacklist.len() < MAX_BLACKLIST,
+            ErrorCode::BlacklistFull
+        );
+        
+        config.blacklist.push(oracle);
+        
+        emit!(OracleBlacklistUpdated {
+            oracle,
+            blacklisted: true,
+        });
+        
+        msg!("Oracle blacklisted: {}", oracle);
+        
+        Ok(())
+    }
+
+    pub fn unblacklist_oracle(ctx: Context<UpdateConfig>, oracle: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        
+        require_keys_eq!(
+            config.admin,
+            ctx.accounts.admin.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        let position = config
+            .blacklist
+            .iter()
+            .position(|blacklisted| *blacklisted == oracle)
+            .ok_or(ErrorCode::OracleNotBlacklisted)?;
+        config.blacklist.swap_remove(position);
+        
+        emit!(OracleBlacklistUpdated {
+            oracle,
+            blacklisted: false,
+        });
+        
+        msg!("Oracle removed from blacklist: {}", oracle);
+        
+        Ok(())
+    }
+
+    pub fn set_provider_status(
+        ctx: Context<UpdateConfig>,
+        provider: String,
+        degraded: bool,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let now = Clock::get()?.unix_timestamp;
+        
+        require_keys_eq!(
+            config.admin,
+            ctx.accounts.admin.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(provider.len() <= 64, ErrorCode::ProviderTooLong);
+        
+        if degraded {
+            let status = provider_status_entry(&mut config.provider_statuses, &provider, now)?;
+            status.degraded_at = Some(now);
+            status.reporters.clear();
+        } else {
+            config
+                .provider_statuses
+                .retain(|status| status.provider != provider);
+        }
+        
+        emit!(ProviderStatusChanged { provider, degraded });
+        
+        Ok(())
+    }
+
+    pub fn report_provider_degraded(ctx: Context<ReportProvider>, provider: String) -> Result<()> {
+        let reporter = ctx.accounts.oracle.key();
+        let now = Clock::get()?.unix_timestamp;
+        
+        require!(
+            is_active_oracle(&ctx.accounts.registry, &reporter),
+            ErrorCode::OracleNotRegistered
+        );
+        
+        require!(provider.len() <= 64, ErrorCode::ProviderTooLong);
+        
+        let config = &mut ctx.accounts.config;
+        let flagged =
+            record_degraded_report(&mut config.provider_statuses, &provider, reporter, now)?;
+        
+        if flagged {
+            emit!(ProviderStatusChanged {
+                provider: provider.clone(),
+                degraded: true,
+            });
+        }
+        
+        msg!("Provider {} reported degraded by oracle: {}", provider, reporter);
+        
+        Ok(())
+    }
+
+    pub fn fulfill_request<'info>(
+        ctx: Context<'_, '_, '_, 'info, FulfillRequest<'info>>,
+        response: Vec<u8>,
+        response_metadata: ResponseMeta,
+        content_type: u8,
+    ) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let callback_program = ctx.accounts.callback_program.key();
+        let response = normalize_response(&request.normalize, &response).to_vec();
+        
+        require!(!request.notify_only, ErrorCode::NotifyOnlyRequest);
+        require!(
+            !request.store_response_onchain || response.len() <= MAX_STORED_RESPONSE,
+            ErrorCode::StoredResponseTooLarge
+        );
+        
+        validate_fulfillment(
+            request,
+            &callback_program,
+            &response,
+            &response_metadata,
+            ctx.remaining_accounts,
+        )?;
+        validate_content_type(request, &response, content_type)?;
+        
+        let attestation = if request.require_attestation {
+            let instructions_sysvar = ctx
+                .accounts
+                .instructions_sysvar
+                .as_ref()
+                .ok_or(ErrorCode::MissingAttestation)?;
+            let oracle = ctx.accounts.oracle.key();
+            let message = attestation_message(
+                &request.id,
+                &response_hash(request, &response, &response_metadata),
+            );
+            let signature = verify_attestation(instructions_sysvar, &oracle, &message)?;
+            
+            Some(OracleAttestation { oracle, signature })
+        } else {
+            None
+        };
+        
+        request.detected_format = response_format(request, &response);
+        
+        let callback_data = build_callback_data(
+            request,
+            &response,
+            &attestation,
+            &response_metadata.model_id,
+            content_type,
+        )?;
+        
+        invoke_callback(request, callback_program, callback_data, ctx.remaining_accounts)?;
+        
+        complete_fulfillment(
+            request,
+            &mut ctx.accounts.event_counter,
+            &response,
+            ctx.accounts.oracle.key(),
+        )?;
+        
+        store_response(request, &response);
+        
+        if writes_result_cache(request) && response.len() <= MAX_CACHED_RESPONSE_LEN {
+            if let Some(cache) = ctx.accounts.result_cache.as_mut() {
+                cache.model_id = request.model_id.clone();
+                cache.response = response.clone();
+                cache.metadata = response_metadata.clone();
+                cache.cached_at = Clock::get()?.unix_timestamp;
+            }
+        }
+        
+        pay_response_fee(request, &ctx.accounts.oracle, response.len())?;
+        
+        collect_protocol_fee(request, &ctx.accounts.config, ctx.accounts.treasury.as_deref())
+    }
+
+    pub fn fulfill_notify_only<'info>(
+        ctx: Context<'_, '_, '_, 'info, FulfillNotifyOnly<'info>>,
+        response: Vec<u8>,
+        response_metadata: ResponseMeta,
+    ) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let callback_program = ctx.accounts.callback_program.key();
+        let response = normalize_response(&request.normalize, &response).to_vec();
+        
+        require!(request.notify_only, ErrorCode::NotNotifyOnlyRequest);
+        require!(!request.require_attestation, ErrorCode::MissingAttestation);
+        
+        validate_fulfillment(
+            request,
+            &callback_program,
+            &response,
+            &response_metadata,
+            ctx.remaining_accounts,
+        )?;
+        
+        let response_buffer = &mut ctx.accounts.response_buffer;
+        response_buffer.request = request.key();
+        response_buffer.response = response.clone();
+        response_buffer.metadata = response_metadata.clone();
+        response_buffer.oracle = ctx.accounts.oracle.key();
+        
+        let callback_data = build_notify_data(request, &response, &response_metadata)?;
+        
+        invoke_callback(request, callback_program, callback_data, ctx.remaining_accounts)?;
+        
+        complete_fulfillment(
+            request,
+            &mut ctx.accounts.event_counter,
+            &response,
+            ctx.accounts.oracle.key(),
+        )?;
+        
+        pay_response_fee(request, &ctx.accounts.oracle, response.len())?;
+        
+        collect_protocol_fee(request, &ctx.accounts.config, ctx.accounts.treasury.as_deref())
+    }
+
+    pub fn init_fulfillment(
+        ctx: Context<InitFulfillment>,
+        total_len: u32,
+        total_chunks: u16,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.request.status == RequestStatus::VotingCompleted,
+            ErrorCode::VotingNotCompleted
+        );
+        require!(
+            total_len > 0
+                && total_len as usize <= MAX_CHUNKED_RESPONSE_LEN
+                && total_chunks > 0
+                && total_chunks as u32 <= total_len,
+            ErrorCode::InvalidChunk
+        );
+        
+        let buffer = &mut ctx.accounts.buffer;
+        buffer.request = ctx.accounts.request.key();
+        buffer.oracle = ctx.accounts.oracle.key();
+        buffer.total_len = total_len;
+        buffer.total_chunks = total_chunks;
+        buffer.next_chunk = 0;
+        buffer.data = Vec::with_capacity(total_len as usize);
+        buffer.bump = ctx.bumps.buffer;
+        
+        Ok(())
+    }
+
+    pub fn append_response_chunk(
+        ctx: Context<AppendResponseChunk>,
+        index: u16,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        let buffer = &mut ctx.accounts.buffer;
+        
+        require_keys_eq!(buffer.oracle, ctx.accounts.oracle.key(), ErrorCode::Unauthorized);
+        
+        require!(
+            index == buffer.next_chunk && index < buffer.total_chunks,
+            ErrorCode::ChunkOutOfOrder
+        );
+        require!(
+            !bytes.is_empty() && buffer.data.len() + bytes.len() <= buffer.total_len as usize,
+            ErrorCode::InvalidChunk
+        );
+        
+        buffer.data.extend_from_slice(&bytes);
+        buffer.next_chunk += 1;
+        
+        Ok(())
+    }
+
+    /// Verifies the assembled chunks against the winning hash and delivers
+    /// them exactly like `fulfill_request`. Attestations are not supported on
+    /// this path. The buffer is closed back to the oracle.
+    pub fn finalize_fulfillment<'info>(
+        ctx: Context<'_, '_, '_, 'info, FinalizeFulfillment<'info>>,
+        response_metadata: ResponseMeta,
+        content_type: u8,
+    ) -> Result<()> {
+        let buffer = &ctx.accounts.buffer;
+        
+        require_keys_eq!(buffer.oracle, ctx.accounts.oracle.key(), ErrorCode::Unauthorized);
+        require!(
+            buffer.next_chunk == buffer.total_chunks
+                && buffer.data.len() == buffer.total_len as usize,
+            ErrorCode::ResponseIncomplete
+        );
+        
+        let request = &mut ctx.accounts.request;
+        let callback_program = ctx.accounts.callback_program.key();
+        let response = normalize_response(&request.normalize, &buffer.data).to_vec();
+        
+        require!(!request.notify_only, ErrorCode::NotifyOnlyRequest);
+        require!(!request.require_attestation, ErrorCode::MissingAttestation);
+        require!(
+            !request.store_response_onchain || response.len() <= MAX_STORED_RESPONSE,
+            ErrorCode::StoredResponseTooLarge
+        );
+        
+        validate_fulfillment(
+            request,
+            &callback_program,
+            &response,
+            &response_metadata,
+            ctx.remaining_accounts,
+        )?;
+        validate_content_type(request, &response, content_type)?;
+        
+        request.detected_format = response_format(request, &response);
+        
+        let callback_data = build_callback_data(
+            request,
+            &response,
+            &None,
+            &response_metadata.model_id,
+            content_type,
+        )?;
+        
+        invoke_callback(request, callback_program, callback_data, ctx.remaining_accounts)?;
+        
+        complete_fulfillment(
+            request,
+            &mut ctx.accounts.event_counter,
+            &response,
+            ctx.accounts.oracle.key(),
+        )?;
+        
+        store_response(request, &response);
+        
+        pay_response_fee(request, &ctx.accounts.oracle, response.len())?;
+        
+        collect_protocol_fee(request, &ctx.accounts.config, ctx.accounts.treasury.as_deref())
+    }
+
+    pub fn fulfill_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FulfillBatch<'info>>,
+        responses: Vec<Vec<u8>>,
+        response_metadata: Vec<ResponseMeta>,
+        content_types: Vec<u8>,
+    ) -> Result<()> {
+        require!(
+            !responses.is_empty() && responses.len() <= MAX_BATCH_FULFILL,
+            ErrorCode::InvalidBatchSize
+        );
+        require!(
+            response_metadata.len() == responses.len() && content_types.len() == responses.len(),
+            ErrorCode::InvalidBatchSize
+        );
+        
+        let callback_program = ctx.accounts.callback_program.key();
+        let fulfiller = ctx.accounts.oracle.key();
+        let mut outcomes = Vec::with_capacity(responses.len());
+        let mut cursor = 0;
+        
+        for ((response, metadata), &content_type) in responses
+            .iter()
+            .zip(response_metadata.iter())
+            .zip(content_types.iter())
+        {
+            require!(
+                cursor < ctx.remaining_accounts.len(),
+                ErrorCode::AccountCountMismatch
+            );
+            
+            let request_info = &ctx.remaining_accounts[cursor];
+            let mut request = Account::<LLMRequest>::try_from(request_info)?;
+            cursor += 1;
+            
+            let callback_count = request.callback_accounts.len();
+            require!(
+                cursor + callback_count <= ctx.remaining_accounts.len(),
+                ErrorCode::AccountCountMismatch
+            );
+            
+            let callback_infos = &ctx.remaining_accounts[cursor..cursor + callback_count];
+            cursor += callback_count;
+            
+            let response = normalize_response(&request.normalize, response);
+            
+            let validation = validate_fulfillment(
+                &request,
+                &callback_program,
+                response,
+                metadata,
+                callback_infos,
+            )
+            .and_then(|()| validate_batch_request(&request, response, content_type))
+            .and_then(|()| {
+                check_fees(
+                    &request,
+                    response.len(),
+                    &ctx.accounts.config,
+                    ctx.accounts.treasury.as_deref(),
+                )
+            });
+            
+            let delivery = validation.and_then(|()| {
+                request.detected_format = response_format(&request, response);
+                let callback_data = build_callback_data(
+                    &request,
+                    response,
+                    &None,
+                    &metadata.model_id,
+                    content_type,
+                )?;
+                invoke_callback(&request, callback_program, callback_data, callback_infos)?;
+                complete_fulfillment(
+                    &mut request,
+                    &mut ctx.accounts.event_counter,
+                    response,
+                    fulfiller,
+                )?;
+                pay_response_fee(&mut request, &ctx.accounts.oracle, response.len())?;
+                collect_protocol_fee(
+                    &mut request,
+                    &ctx.accounts.config,
+                    ctx.accounts.treasury.as_deref(),
+                )?;
+                request.exit(&crate::ID)
+            });
+            
+            let fulfilled = match delivery {
+                Ok(()) => true,
+                Err(error) => {
+                    msg!("Skipping request {}: {}", request.id, error);
+                    false
+                }
+            };
+            
+            outcomes.push(BatchFulfillOutcome {
+                request: request_info.key(),
+                fulfilled,
+            });
+        }
+        
+        require!(
+            cursor == ctx.remaining_accounts.len(),
+            ErrorCode::AccountCountMismatch
+        );
+        
+        emit!(BatchFulfillResult {
+            callback_program,
+            outcomes,
+        });
+        
+        Ok(())
+    }
+
+    pub fn claim_reward(ctx: Context<ClaimReward>) -> Result<()> {
+        let oracle_key = ctx.accounts.oracle.key();
+        let request = &mut ctx.accounts.request;
+        
+        require!(
+            request.status == RequestStatus::Fulfilled,
+            ErrorCode::RequestNotFulfilled
+        );
+        
+        let amount = reward_share(request, &oracle_key)?;
+        
+        let vote = request
+            .votes
+            .iter_mut()
+            .find(|vote| vote.oracle == oracle_key)
+            .ok_or(ErrorCode::NotWinningOracle)?;
+        require!(!vote.reward_claimed, ErrorCode::RewardAlreadyClaimed);
+        vote.reward_claimed = true;
+        
+        let request_id = request.id.clone();
+        
+        **ctx.accounts.request.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.oracle.to_account_info().try_borrow_mut_lamports()? += amount;
+        
+        emit!(RewardClaimed {
+            request_id: request_id.clone(),
+            oracle: oracle_key,
+            amount,
+        });
+        
+        msg!("Reward of {} lamports claimed for request: {}", amount, request_id);
+        
+        Ok(())
+    }
+
+    pub fn acknowledge_delivery(ctx: Context<AcknowledgeDelivery>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let acknowledger = ctx.accounts.acknowledger.key();
+        
+        let (delivery_ack_authority, _) = Pubkey::find_program_address(
+            &[b"delivery_ack", request.id.as_bytes()],
+            &request.caller_program,
+        );
+        require!(
+            acknowledger == request.payer || acknowledger == delivery_ack_authority,
+            ErrorCode::Unauthorized
+        );
+        
+        require!(
+            request.status == RequestStatus::Fulfilled,
+            ErrorCode::RequestNotFulfilled
+        );
+        require!(
+            !request.delivery_acknowledged,
+            ErrorCode::DeliveryAlreadyAcknowledged
+        );
+        
+        let acknowledged_at = Clock::get()?.unix_timestamp;
+        request.delivery_acknowledged = true;
+        request.acknowledged_at = Some(acknowledged_at);
+        
+        emit!(DeliveryAcknowledged {
+            request_id: request.id.clone(),
+            acknowledger,
+            acknowledged_at,
+        });
+        
+        msg!("Delivery acknowledged for request: {}", request.id);
+        
+        Ok(())
+    }
+
+    pub fn authorize_callback_redirect(
+        ctx: Context<AuthorizeCallbackRedirect>,
+        callback_program: Pubkey,
+    ) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        
+        require_keys_eq!(
+            request.payer,
+            ctx.accounts.payer.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        redirect_callback(request, callback_program)?;
+        
+        emit!(CallbackRedirectAuthorized {
+            request_id: request.id.clone(),
+            original_program: request.caller_program,
+            callback_program,
+        });
+        
+        msg!("Callback redirect authorized for request: {}", request.id);
+        
+        Ok(())
+    }
+
+    pub fn reorder_callback_accounts(
+        ctx: Context<ReorderCallbackAccounts>,
+        new_order: Vec<u8>,
+    ) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        
+        require_keys_eq!(
+            request.payer,
+            ctx.accounts.payer.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(
+            request.status == RequestStatus::Pending,
+            ErrorCode::RequestNotPending
+        );
+        
+        let account_count = request.callback_accounts.len();
+        let mut seen = vec![false; account_count];
+        require!(
+            new_order.len() == account_count,
+            ErrorCode::InvalidPermutation
+        );
+        for index in &new_order {
+            let index = *index as usize;
+            require!(
+                index < account_count && !seen[index],
+                ErrorCode::InvalidPermutation
+            );
+            seen[index] = true;
+        }
+        
+        request.callback_accounts = new_order
+            .iter()
+            .map(|index| request.callback_accounts[*index as usize])
+            .collect();
+        request.callback_writable = new_order
+            .iter()
+            .map(|index| request.callback_writable[*index as usize])
+            .collect();
+        
+        emit!(CallbackAccountsReordered {
+            request_id: request.id.clone(),
+            new_order,
+        });
+        
+        msg!("Callback accounts reordered for request: {}", request.id);
+        
+        Ok(())
+    }
+
+    pub fn replace_callback_account(
+        ctx: Context<ReplaceCallbackAccount>,
+        index: u8,
+        new_key: Pubkey,
+    ) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        
+        require_keys_eq!(
+            request.payer,
+            ctx.accounts.payer.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(
+            request.status == RequestStatus::Pending
+                || request.status == RequestStatus::VotingCompleted,
+            ErrorCode::RequestAlreadyFulfilled
+        );
+        
+        require!(
+            (index as usize) < request.callback_accounts.len(),
+            ErrorCode::InvalidCallbackIndex
+        );
+        
+        let old_key = request.callback_accounts[index as usize];
+        request.callback_accounts[index as usize] = new_key;
+        
+        emit!(CallbackAccountReplaced {
+            request_id: request.id.clone(),
+            index,
+            old_key,
+            new_key,
+        });
+        
+        msg!("Callback account {} replaced for request: {}", index, request.id);
+        
+        Ok(())
+    }
+
+    pub fn switch_to_fallback(ctx: Context<SwitchToFallback>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        
+        require_keys_eq!(
+            request.payer,
+            ctx.accounts.payer.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(
+            request.status == RequestStatus::Pending,
+            ErrorCode::RequestNotPending
+        );
+        
+        let expired = deadline_passed(request.expires_at, request.expires_at_slot, &clock);
+        let diverged = request.votes.len() >= request.min_participation as usize
+            || voting_window_closed(request.voting_deadline, now);
+        require!(expired || diverged, ErrorCode::ConsensusStillPossible);
+        
+        let fallback_model_id = request
+            .fallback_model_id
+            .take()
+            .ok_or(ErrorCode::NoFallbackModel)?;
+        
+        request.model_id = fallback_model_id.clone();
+        request.used_fallback = true;
+        request.votes.clear();
+        request.vote_epoch += 1;
+        request.voting_deadline = None;
+        request.total_votes_cast = 0;
+        set_winning_hash(request, None)?;
+        request.peak_vote_count = 0;
+        if let Some(expires_at) = request.expires_at {
+            request.expires_at = Some(now + (expires_at - request.created_at));
+        }
+        if let Some(expires_at_slot) = request.expires_at_slot {
+            request.expires_at_slot = Some(clock.slot + (expires_at_slot - request.created_slot));
+        }
+        
+        emit!(FallbackActivated {
+            request_id: request.id.clone(),
+            fallback_model_id,
+        });
+        
+        msg!("Request switched to fallback model: {}", request.id);
+        
+        Ok(())
+    }
+
+    pub fn cancel_request(ctx: Context<CancelRequest>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        
+        require_keys_eq!(
+            request.payer,
+            ctx.accounts.canceller.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(
+            request.status == RequestStatus::Pending,
+            ErrorCode::RequestNotPending
+        );
+        
+        require!(
+            request.total_votes_cast == 0,
+            ErrorCode::CannotCancelAfterVoting
+        );
+        
+        transition(request, RequestStatus::Cancelled, Clock::get()?.unix_timestamp);
+        
+        emit!(RequestCancelled {
+            request_id: request.id.clone(),
+            forced: false,
+            compensation_lamports: 0,
+            refunded_lamports: request.to_account_info().lamports(),
+        });
+        
+        msg!("Request cancelled: {}", request.id);
+        
+        Ok(())
+    }
+
+    pub fn force_cancel<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelRequest<'info>>,
+        compensate_voters: bool,
+    ) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        
+        require_keys_eq!(
+            request.payer,
+            ctx.accounts.canceller.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(
+            request.status == RequestStatus::Pending,
+            ErrorCode::RequestNotPending
+        );
+        
+        let mut compensation_lamports = 0;
+        
+        if compensate_voters && !request.votes.is_empty() {
+            require!(
+                ctx.remaining_accounts.len() == request.votes.len(),
+                ErrorCode::AccountCountMismatch
+            );
+            
+            let share = request.reward_lamports / request.votes.len() as u64;
+            let request_info = request.to_account_info();
+            
+            for (vote, voter) in request.votes.iter().zip(ctx.remaining_accounts.iter()) {
+                require_keys_eq!(voter.key(), vote.oracle, ErrorCode::AccountMismatch);
+                
+                **request_info.try_borrow_mut_lamports()? -= share;
+                **voter.try_borrow_mut_lamports()? += share;
+                compensation_lamports += share;
+            }
+        }
+        
+        transition(request, RequestStatus::Cancelled, Clock::get()?.unix_timestamp);
+        
+        emit!(RequestCancelled {
+            request_id: request.id.clone(),
+            forced: true,
+            compensation_lamports,
+            refunded_lamports: request.to_account_info().lamports(),
+        });
+        
+        msg!("Request force-cancelled: {}", request.id);
+        
+        Ok(())
+    }
+
+    pub fn expire_request(ctx: Context<ExpireRequest>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let clock = Clock::get()?;
+        
+        require!(
+            matches!(
+                request.status,
+                RequestStatus::Pending | RequestStatus::VotingCompleted | RequestStatus::Disputed
+            ),
+            ErrorCode::RequestNotExpirable
+        );
+        ensure_expiry_reached(request, &clock)?;
+        
+        transition(request, RequestStatus::Expired, clock.unix_timestamp);
+        
+        let keeper_reward = request.keeper_reward_lamports.min(request.reward_lamports);
+        request.reward_lamports -= keeper_reward;
+        
+        if keeper_reward > 0 {
+            **request.to_account_info().try_borrow_mut_lamports()? -= keeper_reward;
+            **ctx.accounts.keeper.to_account_info().try_borrow_mut_lamports()? += keeper_reward;
+        }
+        
+        emit!(RequestExpired {
+            request_id: request.id.clone(),
+            peak_vote_count: request.peak_vote_count,
+            keeper: ctx.accounts.keeper.key(),
+            keeper_reward,
+        });
+        
+        msg!("Request expired: {}", request.id);
+        
+        Ok(())
+    }
+
+    /// Permissionless: once a request has expired, anyone can close it and
+    /// return its rent and remaining escrow to the original payer.
+    pub fn close_expired_request(ctx: Context<CloseExpiredRequest>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        
+        require_keys_eq!(request.payer, ctx.accounts.payer.key(), ErrorCode::Unauthorized);
+        expire_for_refund(request, &Clock::get()?)?;
+        
+        emit!(ExpiredRequestClosed {
+            request_id: request.id.clone(),
+            payer: request.payer,
+            refunded_lamports: request.to_account_info().lamports(),
+        });
+        
+        msg!("Expired request closed: {}", request.id);
+        
+        Ok(())
+    }
+
+    /// Returns a finished request's rent to its payer. Only the payer can
+    /// close it, so a consumer still reading the response decides when it goes.
+    pub fn close_fulfilled_request(ctx: Context<CloseFulfilledRequest>) -> Result<()> {
+        let request = &ctx.accounts.request;
+        
+        require_keys_eq!(request.payer, ctx.accounts.payer.key(), ErrorCode::Unauthorized);
+        ensure_closable(request, Clock::get()?.unix_timestamp)?;
+        
+        emit!(FulfilledRequestClosed {
+            request_id: request.id.clone(),
+            payer: request.payer,
+            status: request.status.clone(),
+            refunded_lamports: request.to_account_info().lamports(),
+        });
+        
+        msg!("Finished request closed: {}", request.id);
+        
+        Ok(())
+    }
+
+    /// Returns a notify-only response buffer's rent to the oracle that paid
+    /// for it. The consumer pulls the response while the request is open, so
+    /// the buffer can only be closed once the request account is gone.
+    pub fn close_response_buffer(ctx: Context<CloseResponseBuffer>) -> Result<()> {
+        require!(
+            ctx.accounts.request.data_is_empty(),
+            ErrorCode::RequestStillOpen
+        );
+        
+        msg!("Response buffer closed for request: {}", ctx.accounts.request.key());
+        
+        Ok(())
+    }
+
+    pub fn pause_request(ctx: Context<SetRequestPaused>, reason: u8) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        
+        authorize_pause(
+            request,
+            load_config(&ctx.accounts.config)?.as_ref(),
+            &ctx.accounts.authority.key(),
+        )?;
+        
+        require!(
+            request.status == RequestStatus::Pending,
+            ErrorCode::RequestNotPending
+        );
+        
+        require!(!request.paused, ErrorCode::RequestPaused);
+        
+        request.paused = true;
+        
+        emit!(RequestPaused {
+            request_id: request.id.clone(),
+            reason,
+        });
+        
+        msg!("Request paused: {}", request.id);
+        
+        Ok(())
+    }
+
+    pub fn resume_request(ctx: Context<SetRequestPaused>, reason: u8) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        
+        authorize_pause(
+            request,
+            load_config(&ctx.accounts.config)?.as_ref(),
+            &ctx.accounts.authority.key(),
+        )?;
+        
+        require!(request.paused, ErrorCode::RequestNotPaused);
+        
+        request.paused = false;
+        
+        emit!(RequestResumed {
+            request_id: request.id.clone(),
+            reason,
+        });
+        
+        msg!("Request resumed: {}", request.id);
+        
+        Ok(())
+    }
+
+    pub fn preview_callback_data(
+        ctx: Context<ViewRequest>,
+        response: Vec<u8>,
+        response_metadata: ResponseMeta,
+        content_type: u8,
+    ) -> Result<Vec<u8>> {
+        build_callback_data(
+            &ctx.accounts.request,
+            &response,
+            &None,
+            &response_metadata.model_id,
+            content_type,
+        )
+    }
+
+    pub fn get_tally(ctx: Context<ViewRequest>) -> Result<Vec<HashTally>> {
+        Ok(hash_tallies(&ctx.accounts.request.votes))
+    }
+
+    pub fn get_request_status(ctx: Context<ViewRequest>) -> Result<RequestStatusView> {
+        let request = &ctx.accounts.request;
+        
+        Ok(RequestStatusView {
+            status: request.status.clone(),
+            min_votes: request.min_votes,
+            total_votes_cast: request.total_votes_cast,
+            peak_vote_count: request.peak_vote_count,
+            winning_hash: request.winning_hash,
+            winning_hash_prefix: request.winning_hash.as_ref().map(hash_prefix),
+            expires_at: request.expires_at,
+            expires_at_slot: request.expires_at_slot,
+        })
+    }
+
+    /// Minimal status view for consumers polling over CPI, stable across
+    /// changes to the `LLMRequest` layout.
+    pub fn get_request_summary(ctx: Context<ViewRequest>) -> Result<RequestSummary> {
+        Ok(request_summary(&ctx.accounts.request))
+    }
+
+    pub fn verify_oracle_vote(
+        ctx: Context<ViewRequest>,
+        oracle: Pubkey,
+        claimed_response: Vec<u8>,
+        claimed_metadata: ResponseMeta,
+    ) -> Result<Option<bool>> {
+        let request = &ctx.accounts.request;
+        let claimed_response = normalize_response(&request.normalize, &claimed_response);
+        
+        Ok(request
+            .votes
+            .iter()
+            .find(|vote| vote.oracle == oracle)
+            .map(|vote| {
+                vote.response_hash == response_hash(request, claimed_response, &claimed_metadata)
+            }))
+    }
+
+    pub fn check_invariants(ctx: Context<ViewRequest>) -> Result<u8> {
+        let request = &ctx.accounts.request;
+        let mut violations = 0;
+        
+        if request.callback_accounts.len() != request.callback_writable.len() {
+            violations |= INVARIANT_CALLBACK_LENGTHS;
+        }
+        
+        if request.votes.len() != request.total_votes_cast as usize {
+            violations |= INVARIANT_VOTE_COUNT;
+        }
+        
+        if request.status == RequestStatus::VotingCompleted && request.winning_hash.is_none() {
+            violations |= INVARIANT_WINNING_HASH;
+        }
+        
+        let has_duplicate = request
+            .votes
+            .iter()
+            .enumerate()
+            .any(|(i, vote)| request.votes[..i].iter().any(|earlier| earlier.oracle == vote.oracle));
+        if has_duplicate {
+            violations |= INVARIANT_DUPLICATE_ORACLE;
+        }
+        
+        if violations != 0 {
+            msg!("Invariant violations for request {}: {:#06b}", request.id, violations);
+        }
+        
+        Ok(violations)
+    }
+
+    pub fn get_timeline(ctx: Context<ViewRequest>) -> Result<RequestTimeline> {
+        let request = &ctx.accounts.request;
+        
+        Ok(RequestTimeline {
+            created_at: request.created_at,
+            first_vote_at: request.votes.iter().map(|vote| vote.voted_at).min(),
+            voting_deadline: request.voting_deadline,
+            voting_completed_at: request.voting_completed_at,
+            fulfilled_at: request.fulfilled_at,
+            expires_at: request.expires_at,
+        })
+    }
+
+    /// True once the winning response has been verified and fulfilled. The
+    /// result cannot change after that.
+    pub fn is_finalized(ctx: Context<ViewRequest>) -> Result<bool> {
+        Ok(ctx.accounts.request.status == RequestStatus::Fulfilled)
+    }
+
+    pub fn get_stored_response(ctx: Context<ViewRequest>) -> Result<Vec<u8>> {
+        ctx.accounts
+            .request
+            .stored_response
+            .clone()
+            .ok_or_else(|| error!(ErrorCode::NoStoredResponse))
+    }
+
+    pub fn get_state_log(ctx: Context<ViewRequest>) -> Result<Vec<StateTransition>> {
+        Ok(ctx.accounts.request.state_log.clone())
+    }
+
+    pub fn time_to_expiry(ctx: Context<ViewRequest>) -> Result<i64> {
+        let expires_at = ctx
+            .accounts
+            .request
+            .expires_at
+            .ok_or(ErrorCode::NoExpiryConfigured)?;
+        
+        Ok(expires_at - Clock::get()?.unix_timestamp)
+    }
+}
+
+/// Runs as a constraint on `payer`, before Anchor's `init` charges the request
+/// account's rent, so the payer must still hold rent plus escrow. The log reports
+/// the full creation cost so callers know how much to fund the payer with.
+fn payer_covers_request(payer: &AccountInfo, escrow: u64) -> Result<bool> {
+    let rent = Rent::get()?.minimum_balance(LLM_REQUEST_SPACE);
+    let needed = rent.saturating_add(escrow);
+    
+    if payer.lamports() < needed {
+        msg!(
+            "Payer needs {} lamports to create a request ({} rent + {} escrow), has {}",
+            needed,
+            rent,
+            escrow,
+            payer.lamports()
+        );
+        return Ok(false);
+    }
+    
+    Ok(true)
+}
+
+/// Rejects callback targets the router must never CPI into: itself, the system
+/// program, the Ed25519 verifier and the sysvars.
+fn validate_callback_program(key: &Pubkey) -> Result<()> {
+    require_keys_neq!(*key, crate::ID, ErrorCode::InvalidCallbackProgram);
+    require!(!is_reserved_program(key), ErrorCode::InvalidCallbackProgram);
+    Ok(())
+}
+
+/// Points delivery at `callback_program` instead of the caller. The new
+/// target goes through the same checks as the one set at creation.
+fn redirect_callback(request: &mut LLMRequest, callback_program: Pubkey) -> Result<()> {
+    require!(
+        request.status == RequestStatus::Pending
+            || request.status == RequestStatus::VotingCompleted,
+        ErrorCode::RequestAlreadyFulfilled
+    );
+    validate_callback_program(&callback_program)?;
+    
+    request.callback_program_override = Some(callback_program);
+    
+    Ok(())
+}
+
+fn is_reserved_program(key: &Pubkey) -> bool {
+    use anchor_lang::solana_program::sysvar;
+    
+    [
+        system_program::ID,
+        ED25519_PROGRAM_ID,
+        sysvar::clock::ID,
+        sysvar::epoch_schedule::ID,
+        sysvar::instructions::ID,
+        sysvar::rent::ID,
+        sysvar::slot_hashes::ID,
+        sysvar::stake_history::ID,
+    ]
+    .contains(key)
+}
+
+/// Length of the base64 `Program data:` log line that `emit!` writes for this event.
+fn encoded_event_len<T: anchor_lang::Event>(event: &T) -> usize {
+    event.data().len().div_ceil(3) * 4
+}
+
+/// Votes are accepted up to, but not at, `voting_deadline`.
+fn voting_window_closed(voting_deadline: Option<i64>, now: i64) -> bool {
+    voting_deadline.is_some_and(|voting_deadline| now >= voting_deadline)
+}
+
+/// A request is expired once the clock is past its deadline: `unix_timestamp >
+/// expires_at` for time-based expiry, or `slot > expires_at_slot` for slot-based.
+fn deadline_passed(expires_at: Option<i64>, expires_at_slot: Option<u64>, clock: &Clock) -> bool {
+    expires_at.is_some_and(|expires_at| clock.unix_timestamp > expires_at)
+        || expires_at_slot.is_some_and(|expires_at_slot| clock.slot > expires_at_slot)
+}
+
+/// The accounts vote gating and recording need, borrowed from either the
+/// direct or the relayed vote context.
+struct VoteAccounts<'a, 'info> {
+    request: &'a mut Account<'info, LLMRequest>,
+    oracle: Pubkey,
+    consumer_cooldown: &'a mut Option<Account<'info, OracleConsumerCooldown>>,
+    config: &'a AccountInfo<'info>,
+    oracle_account: &'a mut Option<Account<'info, OracleAccount>>,
+    registry: &'a Account<'info, OracleRegistry>,
+    event_counter: &'a mut Account<'info, GlobalCounter>,
+}
+
+impl<'info> SubmitVote<'info> {
+    fn vote_accounts(&mut self) -> VoteAccounts<'_, 'info> {
+        VoteAccounts {
+            oracle: self.oracle.key(),
+            request: &mut self.request,
+            consumer_cooldown: &mut self.consumer_cooldown,
+            config: &self.config,
+            oracle_account: &mut self.oracle_account,
+            registry: &self.registry,
+            event_counter: &mut self.event_counter,
+        }
+    }
+}
+
+impl<'info> SubmitVoteRelayed<'info> {
+    fn vote_accounts(&mut self, oracle: Pubkey) -> VoteAccounts<'_, 'info> {
+        VoteAccounts {
+            oracle,
+            request: &mut self.request,
+            consumer_cooldown: &mut self.consumer_cooldown,
+            config: &self.config,
+            oracle_account: &mut self.oracle_account,
+            registry: &self.registry,
+            event_counter: &mut self.event_counter,
+        }
+    }
+}
+
+fn ensure_vote_open(
+    request: &LLMRequest,
+    config: &AccountInfo,
+    response_hash: &[u8; 32],
+    nonce: u64,
+    provider_index: u8,
+    clock: &Clock,
+) -> Result<()> {
+    require!(
+        request.status == RequestStatus::Pending,
+        ErrorCode::VotingClosed
+    );
+    
+    require!(!request.paused, ErrorCode::RequestPaused);
+    
+    ensure_not_paused(load_config(config)?.as_ref())?;
+    
+    require!(
+        !deadline_passed(request.expires_at, request.expires_at_slot, clock),
+        ErrorCode::RequestExpired
+    );
+    
+    require!(
+        !voting_window_closed(request.voting_deadline, clock.unix_timestamp),
+        ErrorCode::VotingWindowClosed
+    );
+    
+    require!(
+        request.votes.len() < MAX_ORACLES,
+        ErrorCode::TooManyVotes
+    );
+    
+    require!(
+        meets_pow_difficulty(response_hash, nonce, request.pow_difficulty),
+        ErrorCode::InsufficientProofOfWork
+    );
+    
+    require!(
+        provider_index as usize <= request.providers.len(),
+        ErrorCode::InvalidProviderIndex
+    );
+    
+    Ok(())
+}
+
+/// What an oracle signs for a relayed vote. The request address and its vote
+/// epoch keep a signature from being replayed on another request or after the
+/// votes are reset.
+fn relayed_vote_message(
+    request: &Pubkey,
+    vote_epoch: u64,
+    response_hash: &[u8; 32],
+    nonce: u64,
+    provider_index: u8,
+) -> Vec<u8> {
+    let mut message = request.to_bytes().to_vec();
+    message.extend_from_slice(&vote_epoch.to_le_bytes());
+    message.extend_from_slice(response_hash);
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message.push(provider_index);
+    message
+}
+
+fn authorize_vote(accounts: &VoteAccounts, now: i64) -> std::result::Result<(), ErrorCode> {
+    let request = &accounts.request;
+    let oracle = accounts.oracle;
+    
+    if request.votes.iter().any(|vote| vote.oracle == oracle) {
+        return Err(ErrorCode::OracleAlreadyVoted);
+    }
+    
+    // A per-request allowlist narrows the registered oracle set; it never
+    // admits an unstaked key, so a payer cannot list itself and vote.
+    if !is_active_oracle(accounts.registry, &oracle) {
+        return Err(ErrorCode::OracleNotRegistered);
+    }
+    
+    if let Some(allowed) = &request.allowed_oracles {
+        if !allowed.contains(&oracle) {
+            return Err(ErrorCode::OracleNotAllowed);
+        }
+    }
+    
+    if request.consumer_cooldown_seconds > 0 {
+        let cooldown = accounts
+            .consumer_cooldown
+            .as_ref()
+            .ok_or(ErrorCode::MissingCooldownAccount)?;
+        
+        if now - cooldown.last_vote_at < request.consumer_cooldown_seconds {
+            return Err(ErrorCode::OracleConsumerCooldown);
+        }
+    }
+    
+    if let Some(config) = load_config(accounts.config)? {
+        if config.blacklist.contains(&oracle) {
+            return Err(ErrorCode::OracleBlacklisted);
+        }
+        
+        if config.daily_vote_cap > 0 {
+            let oracle_account = accounts
+                .oracle_account
+                .as_ref()
+                .ok_or(ErrorCode::MissingOracleAccount)?;
+            
+            if votes_in_bucket(oracle_account, now / SECONDS_PER_DAY) >= config.daily_vote_cap {
+                return Err(ErrorCode::DailyVoteLimitReached);
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// A provider is unavailable while it carries a degraded flag younger than
+/// `PROVIDER_STATUS_TTL_SECONDS`; older flags lapse without an admin reset.
+fn provider_degraded(statuses: &[ProviderStatus], provider: &str, now: i64) -> bool {
+    statuses.iter().any(|status| {
+        status.provider == provider
+            && status
+                .degraded_at
+                .is_some_and(|degraded_at| now < degraded_at + PROVIDER_STATUS_TTL_SECONDS)
+    })
+}
+
+/// Returns the status entry for `provider`, creating it if needed. Entries
+/// with a lapsed flag and no fresh reports are dropped first to make room.
+fn provider_status_entry<'a>(
+    statuses: &'a mut Vec<ProviderStatus>,
+    provider: &str,
+    now: i64,
+) -> std::result::Result<&'a mut ProviderStatus, ErrorCode> {
+    statuses.retain(|status| {
+        status.provider == provider
+            || status
+                .degraded_at
+                .is_some_and(|degraded_at| now < degraded_at + PROVIDER_STATUS_TTL_SECONDS)
+            || (!status.reporters.is_empty()
+                && now < status.first_report_at + PROVIDER_STATUS_TTL_SECONDS)
+    });
+    
+    let index = match statuses.iter().position(|status| status.provider == provider) {
+        Some(index) => index,
+        None => {
+            if statuses.len() >= MAX_PROVIDER_STATUSES {
+                return Err(ErrorCode::ProviderStatusesFull);
+            }
+            statuses.push(ProviderStatus {
+                provider: provider.to_string(),
+                degraded_at: None,
+                reporters: Vec::new(),
+                first_report_at: now,
+            });
+            statuses.len() - 1
+        }
+    };
+    
+    Ok(&mut statuses[index])
+}
+
+/// Adds an oracle's outage report and flags the provider once
+/// `PROVIDER_DEGRADED_QUORUM` distinct oracles have reported within the TTL.
+/// Returns whether this report raised the flag.
+fn record_degraded_report(
+    statuses: &mut Vec<ProviderStatus>,
+    provider: &str,
+    reporter: Pubkey,
+    now: i64,
+) -> std::result::Result<bool, ErrorCode> {
+    let status = provider_status_entry(statuses, provider, now)?;
+    
+    if status.reporters.is_empty() || now >= status.first_report_at + PROVIDER_STATUS_TTL_SECONDS {
+        status.reporters.clear();
+        status.first_report_at = now;
+    }
+    
+    if status.reporters.contains(&reporter) {
+        return Ok(false);
+    }
+    status.reporters.push(reporter);
+    
+    if status.reporters.len() < PROVIDER_DEGRADED_QUORUM {
+        return Ok(false);
+    }
+    
+    status.degraded_at = Some(now);
+    status.reporters.clear();
+    
+    Ok(true)
+}
+
+/// A request can be closed once it is `Fulfilled`, `Expired` or `Cancelled`,
+/// but a fulfilled one only after every rewarded oracle has claimed its share
+/// or `REWARD_CLAIM_WINDOW_SECONDS` have passed. Unclaimed shares then go back
+/// to the payer with the rent.
+fn ensure_closable(request: &LLMRequest, now: i64) -> Result<()> {
+    require!(
+        matches!(
+            request.status,
+            RequestStatus::Fulfilled | RequestStatus::Expired | RequestStatus::Cancelled
+        ),
+        ErrorCode::RequestNotClosable
+    );
+    
+    let claim_window_open = request
+        .fulfilled_at
+        .is_some_and(|fulfilled_at| now < fulfilled_at.saturating_add(REWARD_CLAIM_WINDOW_SECONDS));
+    
+    if request.status == RequestStatus::Fulfilled
+        && request.reward_lamports > 0
+        && claim_window_open
+    {
+        if let Some(winning_hash) = request.winning_hash {
+            require!(
+                request.votes.iter().all(|vote| {
+                    vote.reward_claimed || reward_weight(request, &winning_hash, vote) == 0
+                }),
+                ErrorCode::RewardsUnclaimed
+            );
+        }
+    }
+    
+    Ok(())
+}
+
+/// Checks `close_expired_request` makes before refunding the payer: the
+/// request is already expired, or still pending or disputed past its
+/// deadline, in which case it is expired here.
+fn expire_for_refund(request: &mut LLMRequest, clock: &Clock) -> Result<()> {
+    require!(
+        matches!(
+            request.status,
+            RequestStatus::Pending | RequestStatus::Disputed | RequestStatus::Expired
+        ),
+        ErrorCode::RequestNotExpirable
+    );
+    
+    if request.status != RequestStatus::Expired {
+        ensure_expiry_reached(request, clock)?;
+        transition(request, RequestStatus::Expired, clock.unix_timestamp);
+    }
+    
+    Ok(())
+}
+
+/// Nothing resolves a dispute on-chain, so a disputed request without its
+/// own deadline expires once the challenge window it was raised in closes.
+/// That keeps its escrow from being locked forever.
+fn ensure_expiry_reached(request: &LLMRequest, clock: &Clock) -> Result<()> {
+    let has_deadline = request.expires_at.is_some() || request.expires_at_slot.is_some();
+    
+    if request.status == RequestStatus::Disputed && !has_deadline {
+        require!(
+            !challenge_window_open(
+                request.challenge_period_seconds,
+                request.voting_completed_at,
+                clock.unix_timestamp,
+            ),
+            ErrorCode::RequestNotExpired
+        );
+        return Ok(());
+    }
+    
+    require!(has_deadline, ErrorCode::NoExpiryConfigured);
+    require!(
+        deadline_passed(request.expires_at, request.expires_at_slot, clock),
+        ErrorCode::RequestNotExpired
+    );
+    
+    Ok(())
+}
+
+fn ensure_not_paused(config: Option<&GlobalConfig>) -> std::result::Result<(), ErrorCode> {
+    if config.is_some_and(|config| config.paused) {
+        return Err(ErrorCode::RouterPaused);
+    }
+    
+    Ok(())
+}
+
+/// The payer can pause and resume its own request; the config admin can
+/// pause and resume any request.
+fn authorize_pause(
+    request: &LLMRequest,
+    config: Option<&GlobalConfig>,
+    authority: &Pubkey,
+) -> std::result::Result<(), ErrorCode> {
+    if request.payer == *authority || config.is_some_and(|config| config.admin == *authority) {
+        return Ok(());
+    }
+    
+    Err(ErrorCode::Unauthorized)
+}
+
+/// Reads the global config if it has been initialized. The config address is
+/// fixed by its seeds, so an uninitialized account means no config applies.
+fn load_config(info: &AccountInfo) -> std::result::Result<Option<GlobalConfig>, ErrorCode> {
+    if info.owner != &crate::ID || info.data_is_empty() {
+        return Ok(None);
+    }
+    
+    let data = info.try_borrow_data().map_err(|_| ErrorCode::InvalidConfigAccount)?;
+    GlobalConfig::try_deserialize(&mut &data[..])
+        .map(Some)
+        .map_err(|_| ErrorCode::InvalidConfigAccount)
+}
+
+/// Reads the registry PDA `create_request` takes as its last remaining account
+/// when the request names a trusted oracle.
+fn load_registry(info: &AccountInfo) -> Result<OracleRegistry> {
+    let (registry_address, _) = Pubkey::find_program_address(&[b"registry"], &crate::ID);
+    require_keys_eq!(info.key(), registry_address, ErrorCode::MissingRegistryAccount);
+    require_keys_eq!(*info.owner, crate::ID, ErrorCode::MissingRegistryAccount);
+    
+    let data = info.try_borrow_data()?;
+    OracleRegistry::try_deserialize(&mut &data[..])
+}
+
+/// Returns the cached result for a cache key if the cache PDA has been
+/// populated for the same model within `ttl_seconds`.
+fn load_fresh_cache(
+    info: &AccountInfo,
+    cache_key: &[u8; 32],
+    model_id: &str,
+    ttl_seconds: i64,
+    now: i64,
+) -> Result<Option<ResultCache>> {
+    let (cache_address, _) = Pubkey::find_program_address(&[b"cache", cache_key], &crate::ID);
+    require_keys_eq!(info.key(), cache_address, ErrorCode::MissingCacheAccount);
+    
+    if info.owner != &crate::ID || info.data_is_empty() {
+        return Ok(None);
+    }
+    
+    let data = info.try_borrow_data()?;
+    let cache = ResultCache::try_deserialize(&mut &data[..])?;
+    
+    let fresh = cache.cached_at > 0
+        && cache.model_id == model_id
+        && now - cache.cached_at <= ttl_seconds;
+    
+    Ok(if fresh { Some(cache) } else { None })
+}
+
+/// Seed of the request's result cache PDA; see `coolrouter_cpi::result_cache_key`.
+/// Requests share a cache entry only when the conversation and every
+/// parameter that shapes the response are the same.
+fn result_cache_key(request: &LLMRequest) -> Result<[u8; 32]> {
+    let bytes = (
+        request.conversation_hash,
+        &request.provider,
+        &request.model_id,
+        &request.providers,
+        &request.generation_params,
+        &request.response_constraint,
+        &request.normalize,
+        &request.response_kind,
+    )
+        .try_to_vec()?;
+    
+    Ok(hash(&bytes).to_bytes())
+}
+
+/// Only requests any registered oracle could answer, in plaintext, publish
+/// their result to the shared cache. Allowlisted, trusted-oracle and
+/// encrypted requests can still read it.
+fn writes_result_cache(request: &LLMRequest) -> bool {
+    request.allow_cache
+        && request.allowed_oracles.is_none()
+        && request.trusted_oracle.is_none()
+        && request.encrypt_to.is_none()
+}
+
+fn validate_messages(messages: &[Message]) -> std::result::Result<(), ErrorCode> {
+    if messages.is_empty() {
+        return Err(ErrorCode::EmptyMessages);
+    }
+    
+    for message in messages {
+        if !MESSAGE_ROLES.contains(&message.role.as_str()) {
+            return Err(ErrorCode::InvalidRole);
+        }
+        if message.content.len() > MAX_MESSAGE_CONTENT_LEN {
+            return Err(ErrorCode::MessageContentTooLong);
+        }
+    }
+    
+    Ok(())
+}
+
+fn find_forbidden_substring<'a>(messages: &[Message], forbidden: &'a [String]) -> Option<&'a str> {
+    forbidden
+        .iter()
+        .find(|substring| {
+            messages
+                .iter()
+                .any(|message| message.content.contains(substring.as_str()))
+        })
+        .map(String::as_str)
+}
+
+fn votes_in_bucket(oracle_account: &OracleAccount, day_bucket: i64) -> u32 {
+    if oracle_account.day_bucket == day_bucket {
+        oracle_account.votes_today
+    } else {
+        0
+    }
+}
+
+fn rejection_reason(error: ErrorCode) -> u8 {
+    match error {
+        ErrorCode::OracleAlreadyVoted => 1,
+        ErrorCode::MissingCooldownAccount | ErrorCode::OracleConsumerCooldown => 2,
+        ErrorCode::MissingOracleAccount | ErrorCode::DailyVoteLimitReached => 3,
+        ErrorCode::OracleBlacklisted => 5,
+        ErrorCode::OracleNotRegistered => 6,
+        ErrorCode::OracleNotAllowed => 7,
+        _ => 0,
+    }
+}
+
+fn cast_vote(
+    accounts: &mut VoteAccounts,
+    response_hash: [u8; 32],
+    provider_index: u8,
+    now: i64,
+) -> Result<()> {
+    let oracle_key = accounts.oracle;
+    
+    let weight = vote_weight(accounts.request, accounts.registry, &oracle_key);
+    
+    record_vote(
+        accounts.request,
+        accounts.event_counter,
+        oracle_key,
+        response_hash,
+        weight,
+        provider_index,
+        now,
+    )?;
+    
+    if accounts.request.consumer_cooldown_seconds > 0 {
+        if let Some(cooldown) = accounts.consumer_cooldown.as_mut() {
+            cooldown.last_vote_at = now;
+        }
+    }
+    
+    if let Some(oracle_account) = accounts.oracle_account.as_mut() {
+        let day_bucket = now / SECONDS_PER_DAY;
+        oracle_account.votes_today = votes_in_bucket(oracle_account, day_bucket) + 1;
+        oracle_account.day_bucket = day_bucket;
+    }
+    
+    Ok(())
+}
+
+/// Proof of work for a vote: `sha256(response_hash || nonce.to_le_bytes())` must
+/// start with at least `difficulty` zero bits, counted from the most significant
+/// bit of the first byte. A difficulty of 0 accepts any nonce.
+fn meets_pow_difficulty(response_hash: &[u8; 32], nonce: u64, difficulty: u8) -> bool {
+    let digest = hashv(&[response_hash, &nonce.to_le_bytes()]).to_bytes();
+    let mut zero_bits = 0u32;
+    for byte in digest {
+        zero_bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    zero_bits >= difficulty as u32
+}
+
+/// Stake weights are floored at 1 so a zero-stake registration still counts
+/// and total weight is never zero.
+fn vote_weight(request: &LLMRequest, registry: &OracleRegistry, oracle: &Pubkey) -> u64 {
+    match request.vote_weighting {
+        VoteWeighting::Unweighted => 1,
+        VoteWeighting::Stake => registry
+            .oracles
+            .iter()
+            .find(|entry| entry.oracle == *oracle)
+            .map_or(1, |entry| entry.stake.max(1)),
+    }
+}
+
+/// The only writer of `winning_hash` after creation. Setting a hash latches
+/// `winning_hash_locked`, after which every further write, clearing included,
+/// fails with `WinningHashLocked`.
+fn set_winning_hash(request: &mut LLMRequest, winning_hash: Option<[u8; 32]>) -> Result<()> {
+    require!(!request.winning_hash_locked, ErrorCode::WinningHashLocked);
+    request.winning_hash = winning_hash;
+    request.winning_hash_locked = winning_hash.is_some();
+    Ok(())
+}
+
+fn record_vote(
+    request: &mut LLMRequest,
+    event_counter: &mut GlobalCounter,
+    oracle_key: Pubkey,
+    response_hash: [u8; 32],
+    weight: u64,
+    provider_index: u8,
+    voted_at: i64,
+) -> Result<()> {
+    let total_votes_cast = request
+        .total_votes_cast
+        .checked_add(1)
+        .ok_or(ErrorCode::VoteCountOverflow)?;
+    
+    request.votes.push(OracleVote {
+        oracle: oracle_key,
+        response_hash,
+        voted_at,
+        reward_claimed: false,
+        weight,
+        provider_index,
+    });
+    request.total_votes_cast = total_votes_cast;
+    
+    if request.voting_window_seconds > 0 && request.voting_deadline.is_none() {
+        request.voting_deadline = Some(voted_at + request.voting_window_seconds);
+    }
+    
+    if request.trusted_oracle == Some(oracle_key) {
+        request.peak_vote_count = request.peak_vote_count.max(1);
+        set_winning_hash(request, Some(response_hash))?;
+        transition(request, RequestStatus::VotingCompleted, voted_at);
+        request.voting_completed_at = Some(voted_at);
+        
+        emit!(TrustedOracleCompletion {
+            request_id: request.id.clone(),
+            oracle: oracle_key,
+            winning_hash: response_hash,
+        });
+        
+        msg!("Voting completed by trusted oracle for request: {}", request.id);
+    } else {
+        if let Some((leader, _)) = leading_hash(&request.votes, &request.tie_break) {
+            let leader_count = votes_for(&request.votes, &leader);
+            request.peak_vote_count = request.peak_vote_count.max(leader_count);
+        }
+        
+        let winner = select_winner(
+            &request.votes,
+            &request.tie_break,
+            &request.multi_winner_policy,
+            request.min_votes as u16,
+            |weight| is_approved(request, weight),
+        );
+        
+        if let Some((winning_hash, weight)) = winner {
+            let vote_count = votes_for(&request.votes, &winning_hash);
+            
+            if request.votes.len() >= request.min_participation as usize
+                && vote_count.saturating_sub(runner_up_votes(&request.votes, &winning_hash))
+                    >= request.early_completion_margin as u16
+            {
+                set_winning_hash(request, Some(winning_hash))?;
+                transition(request, RequestStatus::VotingCompleted, voted_at);
+                request.voting_completed_at = Some(voted_at);
+                
+                emit!(VotingCompleted {
+                    seq: next_event_seq(event_counter)?,
+                    request_id: request.id.clone(),
+                    winning_hash,
+                    winning_hash_prefix: hash_prefix(&winning_hash),
+                    vote_count,
+                    total_votes: request.total_votes_cast,
+                    weight,
+                    tallies: hash_tallies(&request.votes),
+                    approval_threshold: request.approval_threshold,
+                });
+                
+                msg!("Voting completed for request: {}", request.id);
+            }
+        }
+    }
+    
+    msg!("Vote submitted by oracle: {}", oracle_key);
+    
+    Ok(())
+}
+
+fn has_sufficient_entropy(response: &[u8]) -> bool {
+    let mut histogram = [0usize; 256];
+    for byte in response {
+        histogram[*byte as usize] += 1;
+    }
+    
+    let distinct = histogram.iter().filter(|count| **count > 0).count();
+    let dominant = histogram.iter().copied().max().unwrap_or(0);
+    
+    distinct >= MIN_DISTINCT_RESPONSE_BYTES
+        && dominant * 100 <= response.len() * MAX_DOMINANT_BYTE_PERCENT
+}
+
+fn is_valid_constraint(constraint: &ResponseConstraint) -> bool {
+    match constraint {
+        ResponseConstraint::OneOf(options) => {
+            !options.is_empty()
+                && options.len() <= MAX_CONSTRAINT_OPTIONS
+                && options.iter().all(|option| option.len() <= MAX_CONSTRAINT_OPTION_LEN)
+        }
+        ResponseConstraint::NumericRange(min, max) => min <= max,
+    }
+}
+
+/// Callback names are Anchor instruction names: lowercase snake_case that
+/// does not start with a digit.
+fn is_valid_callback_ix_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_CALLBACK_IX_NAME_LEN
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+}
+
+/// Anchor discriminator of the consumer instruction `callback_ix_name`.
+fn callback_discriminator(callback_ix_name: &str) -> [u8; 8] {
+    hash(format!("global:{}", callback_ix_name).as_bytes()).to_bytes()[..8]
+        .try_into()
+        .unwrap()
+}
+
+fn is_valid_generation_params(params: &GenerationParams) -> bool {
+    params.temperature <= MAX_TEMPERATURE && params.top_p <= MAX_TOP_P && params.max_tokens > 0
+}
+
+fn satisfies_constraint(constraint: &ResponseConstraint, response: &[u8]) -> bool {
+    match constraint {
+        ResponseConstraint::OneOf(options) => {
+            options.iter().any(|option| option.as_bytes() == response)
+        }
+        ResponseConstraint::NumericRange(min, max) => std::str::from_utf8(response)
+            .ok()
+            .filter(|text| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|text| text.parse::<u64>().ok())
+            .is_some_and(|value| (*min..=*max).contains(&value)),
+    }
+}
+
+fn is_active_oracle(registry: &OracleRegistry, oracle: &Pubkey) -> bool {
+    registry.oracles.iter().any(|entry| {
+        entry.oracle == *oracle
+            && entry.deregistering_at.is_none()
+            && entry.stake >= registry.min_stake
+    })
+}
+
+fn challenge_window_open(period_seconds: i64, voting_completed_at: Option<i64>, now: i64) -> bool {
+    period_seconds > 0
+        && voting_completed_at.is_none_or(|completed_at| now < completed_at + period_seconds)
+}
+
+fn validate_fulfillment(
+    request: &LLMRequest,
+    callback_program: &Pubkey,
+    response: &[u8],
+    metadata: &ResponseMeta,
+    callback_infos: &[AccountInfo],
+) -> Result<()> {
+    require!(
+        request.status == RequestStatus::VotingCompleted,
+        ErrorCode::VotingNotCompleted
+    );
+    
+    let winning_hash = request.winning_hash.ok_or(ErrorCode::NoWinningHash)?;
+    
+    require!(
+        !challenge_window_open(
+            request.challenge_period_seconds,
+            request.voting_completed_at,
+            Clock::get()?.unix_timestamp,
+        ),
+        ErrorCode::ChallengeWindowOpen
+    );
+    
+    validate_response_meta(metadata)?;
+    
+    if request.require_content_entropy {
+        require!(
+            has_sufficient_entropy(response),
+            ErrorCode::ResponseEntropyTooLow
+        );
+    }
+    
+    if let Some(constraint) = &request.response_constraint {
+        require!(
+            satisfies_constraint(constraint, response),
+            ErrorCode::ResponseConstraintViolated
+        );
+    }
+    
+    require!(
+        response_hash(request, response, metadata) == winning_hash,
+        ErrorCode::ResponseHashMismatch
+    );
+    
+    response_fee(request, response.len())?;
+    
+    require!(
+        *callback_program == request.caller_program
+            || request.callback_program_override == Some(*callback_program),
+        ErrorCode::CallbackProgramMismatch
+    );
+    
+    require!(
+        callback_infos.len() == request.callback_accounts.len(),
+        ErrorCode::AccountCountMismatch
+    );
+    
+    for (i, expected_key) in request.callback_accounts.iter().enumerate() {
+        require!(
+            callback_infos[i].key() == *expected_key,
+            ErrorCode::AccountMismatch
+        );
+    }
+    
+    Ok(())
+}
+
+/// With `TrimTrailingWhitespace`, trailing bytes matching `u8::is_ascii_whitespace`
+/// (space, `\t`, `\n`, `\x0C`, `\r`) are removed before hashing and delivery.
+fn normalize_response<'a>(mode: &NormalizeMode, response: &'a [u8]) -> &'a [u8] {
+    match mode {
+        NormalizeMode::Raw => response,
+        NormalizeMode::TrimTrailingWhitespace => response.trim_ascii_end(),
+    }
+}
+
+/// Oracles vote on `sha256(coolrouter_cpi::canonical_response_bytes(request_id,
+/// response, metadata))`, the Borsh encoding of `(request_id, response, metadata)`.
+/// Every field is length-prefixed, so no two distinct triples share a preimage.
+/// Multi-provider requests hash an empty `model_id`, so oracles answering
+/// through different targets can agree; `provider_index` on the vote records
+/// which target each one used.
+fn response_hash(request: &LLMRequest, response: &[u8], metadata: &ResponseMeta) -> [u8; 32] {
+    let model_id = if request.providers.is_empty() {
+        metadata.model_id.clone()
+    } else {
+        String::new()
+    };
+    let metadata = coolrouter_cpi::ResponseMeta {
+        model_id,
+        finish_reason: metadata.finish_reason.clone(),
+    };
+    hash(&coolrouter_cpi::canonical_response_bytes(&request.id, response, &metadata)).to_bytes()
+}
+
+fn validate_response_meta(metadata: &ResponseMeta) -> Result<()> {
+    require!(
+        metadata.model_id.len() <= MAX_MODEL_ID_LEN,
+        ErrorCode::ModelIdTooLong
+    );
+    require!(
+        metadata.finish_reason.len() <= MAX_FINISH_REASON_LEN,
+        ErrorCode::FinishReasonTooLong
+    );
+    Ok(())
+}
+
+fn attestation_message(request_id: &str, response_hash: &[u8; 32]) -> Vec<u8> {
+    let mut message = request_id.as_bytes().to_vec();
+    message.extend_from_slice(response_hash);
+    message
+}
+
+fn verify_attestation(
+    instructions_sysvar: &AccountInfo,
+    oracle: &Pubkey,
+    message: &[u8],
+) -> Result<[u8; 64]> {
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    require!(current_index > 0, ErrorCode::MissingAttestation);
+    
+    let ed25519_ix = load_instruction_at_checked((current_index - 1) as usize, instructions_sysvar)?;
+    require_keys_eq!(
+        ed25519_ix.program_id,
+        ED25519_PROGRAM_ID,
+        ErrorCode::MissingAttestation
+    );
+    
+    let data = &ed25519_ix.data;
+    require!(
+        data.len() >= ED25519_HEADER_LEN && data[0] == 1,
+        ErrorCode::InvalidAttestation
+    );
+    
+    let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    
+    require!(
+        read_u16(4) == u16::MAX && read_u16(8) == u16::MAX && read_u16(14) == u16::MAX,
+        ErrorCode::InvalidAttestation
+    );
+    
+    let signature_offset = read_u16(2) as usize;
+    let public_key_offset = read_u16(6) as usize;
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+    
+    let signature = data
+        .get(signature_offset..signature_offset + 64)
+        .ok_or(ErrorCode::InvalidAttestation)?;
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(ErrorCode::InvalidAttestation)?;
+    let signed_message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(ErrorCode::InvalidAttestation)?;
+    
+    require!(
+        public_key == oracle.as_ref() && signed_message == message,
+        ErrorCode::InvalidAttestation
+    );
+    
+    Ok(signature.try_into().unwrap())
+}
+
+fn build_callback_data(
+    request: &LLMRequest,
+    response: &[u8],
+    attestation: &Option<OracleAttestation>,
+    served_model_id: &str,
+    content_type: u8,
+) -> Result<Vec<u8>> {
+    let discriminator = callback_discriminator(&request.callback_ix_name);
+    
+    let mut callback_data: Vec<u8> = discriminator.to_vec();
+    callback_data.extend_from_slice(
+        &(
+            request.id.clone(),
+            response.to_vec(),
+            attestation.clone(),
+            request.conversation_hash,
+            served_model_id.to_string(),
+            request.response_kind.clone(),
+            response_format(request, response),
+            vote_distribution(request),
+            content_type,
+        )
+            .try_to_vec()?,
+    );
+    
+    Ok(callback_data)
+}
+
+/// Coarse content sniff: JSON if the UTF-8 text opens with `{` or `[`, text
+/// for any other UTF-8, binary otherwise. Nothing is parsed.
+fn detect_response_format(response: &[u8]) -> u8 {
+    match std::str::from_utf8(response) {
+        Ok(text) if text.trim_start().starts_with(['{', '[']) => FORMAT_JSON,
+        Ok(_) => FORMAT_TEXT,
+        Err(_) => FORMAT_BINARY,
+    }
+}
+
+/// `content_type` is the fulfilling oracle's declaration of how to decode the
+/// response. With format detection on it must agree with the detected format.
+fn validate_content_type(request: &LLMRequest, response: &[u8], content_type: u8) -> Result<()> {
+    require!(content_type <= CONTENT_TYPE_BINARY, ErrorCode::InvalidContentType);
+    
+    if request.detect_format {
+        let detected = match detect_response_format(response) {
+            FORMAT_JSON => CONTENT_TYPE_JSON,
+            FORMAT_BINARY => CONTENT_TYPE_BINARY,
+            _ => CONTENT_TYPE_TEXT,
+        };
+        require!(content_type == detected, ErrorCode::ContentTypeMismatch);
+    }
+    
+    Ok(())
+}
+
+fn response_format(request: &LLMRequest, response: &[u8]) -> u8 {
+    if request.detect_format {
+        detect_response_format(response)
+    } else {
+        FORMAT_UNDETECTED
+    }
+}
+
+fn vote_distribution(request: &LLMRequest) -> Option<Vec<HashTally>> {
+    request
+        .rich_distribution_callback
+        .then(|| hash_tallies(&request.votes))
+}
+
+fn build_notify_data(
+    request: &LLMRequest,
+    response: &[u8],
+    metadata: &ResponseMeta,
+) -> Result<Vec<u8>> {
+    let discriminator: [u8; 8] = hash(b"global:llm_notify")
+        .to_bytes()[..8]
+        .try_into()
+        .unwrap();
+    
+    let mut callback_data: Vec<u8> = discriminator.to_vec();
+    callback_data.extend_from_slice(
+        &(
+            request.id.clone(),
+            response_hash(request, response, metadata),
+            response.len() as u64,
+        )
+            .try_to_vec()?,
+    );
+    
+    Ok(callback_data)
+}
+
+fn invoke_callback(
+    request: &LLMRequest,
+    callback_program: Pubkey,
+    callback_data: Vec<u8>,
+    callback_infos: &[AccountInfo],
+) -> Result<()> {
+    let mut account_metas = vec![];
+    for (i, pubkey) in request.callback_accounts.iter().enumerate() {
+        account_metas.push(AccountMeta {
+            pubkey: *pubkey,
+            is_signer: false,
+            is_writable: request.callback_writable[i],
+        });
+    }
+    
+    let ix = Instruction {
+        program_id: callback_program,
+        accounts: account_metas,
+        data: callback_data,
+    };
+    
+    invoke(
+        &ix,
+        callback_infos,
+    )?;
+    
+    Ok(())
+}
+
+/// Sets the request status and records the transition in its state log. The
+/// log is sized for every transition a request can go through, so the bound
+/// is never hit in practice.
+fn transition(request: &mut LLMRequest, status: RequestStatus, at: i64) {
+    if request.state_log.len() < MAX_STATE_LOG {
+        request.state_log.push(StateTransition {
+            status: status.clone(),
+            at,
+        });
+    }
+    request.status = status;
+}
+
+/// Hands out the next value of the global event sequence, starting at 1.
+/// `RequestCreated`, `VotingCompleted` and `RequestFulfilled` carry it so
+/// indexers can order them across requests without relying on slots.
+fn next_event_seq(event_counter: &mut GlobalCounter) -> Result<u64> {
+    event_counter.value = event_counter
+        .value
+        .checked_add(1)
+        .ok_or(ErrorCode::EventSeqOverflow)?;
+    Ok(event_counter.value)
+}
+
+fn complete_fulfillment(
+    request: &mut LLMRequest,
+    event_counter: &mut GlobalCounter,
+    response: &[u8],
+    fulfiller: Pubkey,
+) -> Result<()> {
+    let fulfilled_at = Clock::get()?.unix_timestamp;
+    transition(request, RequestStatus::Fulfilled, fulfilled_at);
+    request.fulfilled_by = Some(fulfiller);
+    request.fulfilled_at = Some(fulfilled_at);
+    
+    emit!(RequestFulfilled {
+        seq: next_event_seq(event_counter)?,
+        request_id: request.id.clone(),
+        response_length: response.len() as u64,
+    });
+    
+    msg!("Request fulfilled: {}", request.id);
+    
+    Ok(())
+}
+
+/// Keeps the delivered response on the request for `get_stored_response`
+/// when it was created with `store_response_onchain`.
+fn store_response(request: &mut LLMRequest, response: &[u8]) {
+    if request.store_response_onchain {
+        request.stored_response = Some(response.to_vec());
+    }
+}
+
+/// Checks `fulfill_batch` adds on top of `validate_fulfillment`. Requests that
+/// store the response on-chain are skipped: the batch has no way to grow them.
+fn validate_batch_request(request: &LLMRequest, response: &[u8], content_type: u8) -> Result<()> {
+    require!(!request.require_attestation, ErrorCode::MissingAttestation);
+    require!(!request.notify_only, ErrorCode::NotifyOnlyRequest);
+    require!(!request.store_response_onchain, ErrorCode::StoredResponseNotBatchable);
+    validate_content_type(request, response, content_type)
+}
+
+/// Account size needed to hold the response when the request stores it
+/// on-chain. The length is clamped so an oversized response reaches the
+/// handler's `StoredResponseTooLarge` check instead of failing the realloc.
+fn stored_response_space(request: &Account<LLMRequest>, response_len: usize) -> usize {
+    if request.store_response_onchain {
+        LLM_REQUEST_SPACE + 4 + response_len.min(MAX_STORED_RESPONSE)
+    } else {
+        request.to_account_info().data_len()
+    }
+}
+
+fn response_fee(request: &LLMRequest, response_len: usize) -> Result<u64> {
+    (response_len as u64)
+        .checked_mul(request.fee_per_response_byte)
+        .filter(|fee| *fee <= request.reward_lamports)
+        .ok_or_else(|| error!(ErrorCode::InsufficientFeeForResponse))
+}
+
+/// Moves the size-based response fee out of the reward pool to the fulfiller
+/// before winners claim their shares.
+fn pay_response_fee<'info>(
+    request: &mut Account<'info, LLMRequest>,
+    fulfiller: &AccountInfo<'info>,
+    response_len: usize,
+) -> Result<()> {
+    let fee = response_fee(request, response_len)?;
+    if fee == 0 {
+        return Ok(());
+    }
+    
+    request.reward_lamports -= fee;
+    **request.to_account_info().try_borrow_mut_lamports()? -= fee;
+    **fulfiller.try_borrow_mut_lamports()? += fee;
+    
+    emit!(ResponseFeePaid {
+        request_id: request.id.clone(),
+        fulfiller: fulfiller.key(),
+        fee,
+    });
+    
+    Ok(())
+}
+
+/// Sends `protocol_fee_bps` of the remaining reward pool to the configured
+/// treasury. Runs after the response fee, before winners claim their shares.
+fn collect_protocol_fee<'info>(
+    request: &mut Account<'info, LLMRequest>,
+    config_info: &AccountInfo<'info>,
+    treasury: Option<&AccountInfo<'info>>,
+) -> Result<()> {
+    let config = match load_config(config_info)? {
+        Some(config) if config.protocol_fee_bps > 0 => config,
+        _ => return Ok(()),
+    };
+    
+    let amount = protocol_fee(request.reward_lamports, &config);
+    if amount == 0 {
+        return Ok(());
+    }
+    
+    let treasury = treasury.ok_or(ErrorCode::InvalidTreasury)?;
+    require_keys_eq!(treasury.key(), config.treasury, ErrorCode::InvalidTreasury);
+    
+    request.reward_lamports -= amount;
+    **request.to_account_info().try_borrow_mut_lamports()? -= amount;
+    **treasury.try_borrow_mut_lamports()? += amount;
+    
+    emit!(ProtocolFeeCollected {
+        request_id: request.id.clone(),
+        treasury: config.treasury,
+        amount,
+    });
+    
+    Ok(())
+}
+
+fn protocol_fee(reward_lamports: u64, config: &GlobalConfig) -> u64 {
+    ((reward_lamports as u128 * config.protocol_fee_bps as u128) / MAX_BPS as u128) as u64
+}
+
+/// Runs the checks `pay_response_fee` and `collect_protocol_fee` would fail
+/// on, without moving lamports, so `fulfill_batch` can skip a request before
+/// its callback runs instead of after.
+fn check_fees(
+    request: &LLMRequest,
+    response_len: usize,
+    config_info: &AccountInfo,
+    treasury: Option<&AccountInfo>,
+) -> Result<()> {
+    let fee = response_fee(request, response_len)?;
+    let config = match load_config(config_info)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    
+    if protocol_fee(request.reward_lamports - fee, &config) > 0 {
+        let treasury = treasury.ok_or(ErrorCode::InvalidTreasury)?;
+        require_keys_eq!(treasury.key(), config.treasury, ErrorCode::InvalidTreasury);
+    }
+    
+    Ok(())
+}
+
+fn reward_share(request: &LLMRequest, oracle: &Pubkey) -> Result<u64> {
+    let winning_hash = request.winning_hash.ok_or(ErrorCode::NoWinningHash)?;
+    let weight_of = |key: &Pubkey| {
+        request
+            .votes
+            .iter()
+            .find(|vote| vote.oracle == *key)
+            .map_or(0, |vote| reward_weight(request, &winning_hash, vote))
+    };
+    
+    let weight = weight_of(oracle);
+    require!(weight > 0, ErrorCode::NotWinningOracle);
+    
+    let total_weight: u64 = request
+        .votes
+        .iter()
+        .map(|vote| reward_weight(request, &winning_hash, vote))
+        .sum();
+    
+    let bonus = match request.fulfilled_by {
+        Some(fulfiller) if weight_of(&fulfiller) == MAX_BPS as u64 => {
+            ((request.reward_lamports as u128 * request.fulfiller_bonus_bps as u128)
+                / MAX_BPS as u128) as u64
+        }
+        _ => 0,
+    };
+    let base = ((request.reward_lamports - bonus) as u128 * weight as u128
+        / total_weight as u128) as u64;
+    
+    if request.fulfilled_by == Some(*oracle) {
+        Ok(base + bonus)
+    } else {
+        Ok(base)
+    }
+}
+
+/// Reward weight of a vote in basis points: winners get the full weight, and
+/// under `RewardMode::TopCluster` voters for any other hash with at least
+/// `MIN_CLUSTER_VOTES` votes get `MINORITY_REWARD_WEIGHT_BPS`.
+fn reward_weight(request: &LLMRequest, winning_hash: &[u8; 32], vote: &OracleVote) -> u64 {
+    if vote.response_hash == *winning_hash {
+        return MAX_BPS as u64;
+    }
+    
+    let cluster_size = votes_for(&request.votes, &vote.response_hash);
+    
+    match request.reward_mode {
+        RewardMode::TopCluster if cluster_size >= MIN_CLUSTER_VOTES => {
+            MINORITY_REWARD_WEIGHT_BPS as u64
+        }
+        _ => 0,
+    }
+}
+
+/// Summed vote weight per hash, in first-seen order. Unweighted votes have
+/// weight 1, so this is a plain count for those requests.
+fn count_votes(votes: &[OracleVote]) -> Vec<([u8; 32], u64)> {
+    let mut hash_weights: Vec<([u8; 32], u64)> = Vec::new();
+    
+    for vote in votes {
+        let mut found = false;
+        for (hash, weight) in hash_weights.iter_mut() {
+            if *hash == vote.response_hash {
+                *weight += vote.weight;
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            hash_weights.push((vote.response_hash, vote.weight));
+        }
+    }
+    
+    hash_weights
+}
+
+/// First four bytes of a response hash, big-endian, for cheap client-side
+/// filtering before comparing the full hash.
+fn hash_prefix(hash: &[u8; 32]) -> u32 {
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]])
+}
+
+fn hash_tallies(votes: &[OracleVote]) -> Vec<HashTally> {
+    count_votes(votes)
+        .into_iter()
+        .take(MAX_ORACLES)
+        .map(|(response_hash, weight)| HashTally {
+            response_hash,
+            count: votes_for(votes, &response_hash),
+            weight,
+        })
+        .collect()
+}
+
+/// The leader is the winning hash once voting completed, and the current
+/// leading hash before that.
+fn request_summary(request: &LLMRequest) -> RequestSummary {
+    let leader = request
+        .winning_hash
+        .or_else(|| leading_hash(&request.votes, &request.tie_break).map(|(hash, _)| hash));
+    
+    RequestSummary {
+        status: request.status.clone(),
+        total_votes_cast: request.total_votes_cast,
+        winning_hash: request.winning_hash,
+        votes_for_leader: leader.map_or(0, |leader| votes_for(&request.votes, &leader)),
+    }
+}
+
+fn votes_for(votes: &[OracleVote], response_hash: &[u8; 32]) -> u16 {
+    votes.iter().filter(|vote| vote.response_hash == *response_hash).count() as u16
+}
+
+fn is_approved(request: &LLMRequest, weight: u64) -> bool {
+    let total_weight: u64 = request.votes.iter().map(|vote| vote.weight).sum();
+    
+    match request.approval_kind {
+        ApprovalKind::Threshold => threshold_met(
+            weight,
+            total_weight,
+            request.approval_threshold,
+            &request.threshold_rounding,
+        ),
+        ApprovalKind::StrictMajority => weight as u128 * 2 > total_weight as u128,
+        ApprovalKind::Plurality => count_votes(&request.votes)
+            .iter()
+            .filter(|(_, tally)| *tally >= weight)
+            .count()
+            == 1,
+    }
+}
+
+fn threshold_met(
+    weight: u64,
+    total_weight: u64,
+    approval_threshold: u8,
+    rounding: &RoundingMode,
+) -> bool {
+    // Stake weights are lamports, so `weight * 100` can exceed u64.
+    let votes = weight as u128 * 100;
+    let total = total_weight as u128;
+    let threshold = approval_threshold as u128;
+    
+    match rounding {
+        RoundingMode::Floor => votes / total >= threshold,
+        RoundingMode::Ceil => votes.div_ceil(total) >= threshold,
+        RoundingMode::Exact => votes >= threshold * total,
+    }
+}
+
+/// Returns the most-voted hash. Equal counts are broken by `tie_break`:
+/// `Lexicographic` picks the smallest hash bytes, so the winner never depends
+/// on the order oracles submitted in.
+fn leading_hash(votes: &[OracleVote], tie_break: &TieBreak) -> Option<([u8; 32], u64)> {
+    count_votes(votes).into_iter().max_by(|a, b| {
+        a.1.cmp(&b.1).then_with(|| match tie_break {
+            TieBreak::Lexicographic => b.0.cmp(&a.0),
+            TieBreak::EarliestFirstVote => first_vote(votes, &b.0).cmp(&first_vote(votes, &a.0)),
+        })
+    })
+}
+
+/// Picks the hash that completes voting among those with at least
+/// `min_votes` votes whose weight `approved` accepts. `min_votes` is a head
+/// count under every `VoteWeighting`. `HighestCount` only ever
+/// completes on the leader, `FirstToThreshold` on whichever qualifying hash
+/// reached `min_votes` in the fewest votes, and `RejectAmbiguous` only when
+/// exactly one hash qualifies.
+fn select_winner(
+    votes: &[OracleVote],
+    tie_break: &TieBreak,
+    policy: &MultiWinnerPolicy,
+    min_votes: u16,
+    approved: impl Fn(u64) -> bool,
+) -> Option<([u8; 32], u64)> {
+    let qualifies = |(hash, weight): &([u8; 32], u64)| {
+        votes_for(votes, hash) >= min_votes && approved(*weight)
+    };
+    
+    match policy {
+        MultiWinnerPolicy::HighestCount => leading_hash(votes, tie_break).filter(qualifies),
+        MultiWinnerPolicy::FirstToThreshold => count_votes(votes)
+            .into_iter()
+            .filter(qualifies)
+            .min_by_key(|(hash, _)| threshold_reached_at(votes, hash, min_votes)),
+        MultiWinnerPolicy::RejectAmbiguous => {
+            let mut qualifying = count_votes(votes).into_iter().filter(qualifies);
+            match (qualifying.next(), qualifying.next()) {
+                (Some(winner), None) => Some(winner),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Index of the vote that brought `response_hash` to `min_votes` votes.
+fn threshold_reached_at(votes: &[OracleVote], response_hash: &[u8; 32], min_votes: u16) -> usize {
+    let mut count = 0;
+    for (i, vote) in votes.iter().enumerate() {
+        if vote.response_hash == *response_hash {
+            count += 1;
+            if count >= min_votes {
+                return i;
+            }
+        }
+    }
+    usize::MAX
+}
+
+/// Head count of the best-supported hash other than `leader`.
+fn runner_up_votes(votes: &[OracleVote], leader: &[u8; 32]) -> u16 {
+    count_votes(votes)
+        .into_iter()
+        .filter(|(hash, _)| hash != leader)
+        .map(|(hash, _)| votes_for(votes, &hash))
+        .max()
+        .unwrap_or(0)
+}
+
+fn first_vote(votes: &[OracleVote], response_hash: &[u8; 32]) -> (i64, usize) {
+    votes
+        .iter()
+        .enumerate()
+        .filter(|(_, vote)| vote.response_hash == *response_hash)
+        .map(|(i, vote)| (vote.voted_at, i))
+        .min()
+        .unwrap_or((i64::MAX, usize::MAX))
+}
+
+const LLM_REQUEST_SPACE: usize = 8 + 64 + 32 + 64 + 64
+    + (4 + 32 * MAX_CALLBACK_ACCOUNTS)
+    + (4 + MAX_CALLBACK_ACCOUNTS)
+    + 1 + 8 + 1 + 1
+    + (4 + 82 * MAX_ORACLES)
+    + (1 + 32)
+    + 2
+    + 1
+    + 32
+    + (1 + 32)
+    + 1
+    + 1
+    + 1
+    + 8
+    + 2
+    + (1 + 32)
+    + 32
+    + 8
+    + (1 + 8)
+    + 1
+    + 1
+    + 1
+    + 1
+    + 1
+    + 2
+    + (1 + 32)
+    + 1
+    + 1
+    + (1 + 8)
+    + (1 + 4 + MAX_MODEL_ID_LEN)
+    + 1
+    + 8
+    + (4 + 32 * MAX_DECLINED)
+    + (1 + 32)
+    + 8
+    + 1
+    + 8
+    + (1 + 8)
+    + (1 + 8)
+    + (1 + 8)
+    + 1
+    + (1 + 4 + 32 * MAX_ORACLES)
+    + 1
+    + 1
+    + (4 + 9 * MAX_STATE_LOG)
+    + 1
+    + (1 + 1 + 4 + MAX_CONSTRAINT_OPTIONS * (4 + MAX_CONSTRAINT_OPTION_LEN))
+    + 1
+    + 1
+    + 1
+    + 8
+    + (1 + 32)
+    + 1
+    + 8
+    + (4 + MAX_PROVIDERS * (4 + 64 + 4 + MAX_MODEL_ID_LEN))
+    + 1
+    + (1 + 2 + 4 + 2 + (1 + 8))
+    + 8
+    + (1 + 8)
+    + 1
+    + (4 + MAX_CALLBACK_IX_NAME_LEN)
+    + 32
+    + 1;
+
+#[derive(Accounts)]
+#[instruction(request_id: String, params: CreateRequestParams)]
+pub struct CreateRequest<'info> {
+    /// Listed before `request` so the balance check runs before `init` charges rent.
+    #[account(
+        mut,
+        constraint = payer_covers_request(&payer, params.reward_lamports)?
+            @ ErrorCode::InsufficientPayerBalance
+    )]
+    pub payer: Signer<'info>,
+    #[account(
+        init,
+        payer = payer,
+        space = LLM_REQUEST_SPACE,
+        seeds = [b"request", request_id.as_bytes()],
+        bump
+    )]
+    pub request: Account<'info, LLMRequest>,
+    /// CHECK: The calling program
+    pub caller_program: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Global config PDA, may be uninitialized; read via load_config
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"event_counter"], bump = event_counter.bump)]
+    pub event_counter: Account<'info, GlobalCounter>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitVote<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    pub oracle: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"cooldown", oracle.key().as_ref(), request.caller_program.as_ref()],
+        bump = consumer_cooldown.bump
+    )]
+    pub consumer_cooldown: Option<Account<'info, OracleConsumerCooldown>>,
+    /// CHECK: Global config PDA, may be uninitialized; read via load_config
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"oracle", oracle.key().as_ref()],
+        bump = oracle_account.bump
+    )]
+    pub oracle_account: Option<Account<'info, OracleAccount>>,
+    #[account(seeds = [b"registry"], bump = registry.bump)]
+    pub registry: Account<'info, OracleRegistry>,
+    #[account(mut, seeds = [b"event_counter"], bump = event_counter.bump)]
+    pub event_counter: Account<'info, GlobalCounter>,
+}
+
+#[derive(Accounts)]
+#[instruction(oracle: Pubkey)]
+pub struct SubmitVoteRelayed<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    pub relayer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"cooldown", oracle.as_ref(), request.caller_program.as_ref()],
+        bump = consumer_cooldown.bump
+    )]
+    pub consumer_cooldown: Option<Account<'info, OracleConsumerCooldown>>,
+    /// CHECK: Global config PDA, may be uninitialized; read via load_config
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"oracle", oracle.as_ref()],
+        bump = oracle_account.bump
+    )]
+    pub oracle_account: Option<Account<'info, OracleAccount>>,
+    #[account(seeds = [b"registry"], bump = registry.bump)]
+    pub registry: Account<'info, OracleRegistry>,
+    /// CHECK: Instructions sysvar holding the oracle's ed25519 verification
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"event_counter"], bump = event_counter.bump)]
+    pub event_counter: Account<'info, GlobalCounter>,
+}
+
+#[derive(Accounts)]
+pub struct DeclineRequest<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    pub oracle: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RaiseDispute<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    #[account(seeds = [b"registry"], bump = registry.bump)]
+    pub registry: Account<'info, OracleRegistry>,
+    pub oracle: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(caller_program: Pubkey)]
+pub struct InitOracleCooldown<'info> {
+    #[account(
+        init,
+        payer = oracle,
+        space = 8 + 32 + 32 + 8 + 1,
+        seeds = [b"cooldown", oracle.key().as_ref(), caller_program.as_ref()],
+        bump
+    )]
+    pub cooldown: Account<'info, OracleConsumerCooldown>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeRegistry<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 8 + 8
+            + (4 + MAX_REGISTERED_ORACLES * (32 + 8 + (1 + 8)))
+            + 1,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub registry: Account<'info, OracleRegistry>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 1,
+        seeds = [b"stake_vault"],
+        bump
+    )]
+    pub vault: Account<'info, StakeVault>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRegistry<'info> {
+    #[account(mut, seeds = [b"registry"], bump = registry.bump)]
+    pub registry: Account<'info, OracleRegistry>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterOracle<'info> {
+    #[account(mut, seeds = [b"registry"], bump = registry.bump)]
+    pub registry: Account<'info, OracleRegistry>,
+    #[account(mut, seeds = [b"stake_vault"], bump = vault.bump)]
+    pub vault: Account<'info, StakeVault>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DeregisterOracle<'info> {
+    #[account(mut, seeds = [b"registry"], bump = registry.bump)]
+    pub registry: Account<'info, OracleRegistry>,
+    #[account(mut, seeds = [b"stake_vault"], bump = vault.bump)]
+    pub vault: Account<'info, StakeVault>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(cache_key: [u8; 32])]
+pub struct InitResultCache<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 32 + (4 + MAX_MODEL_ID_LEN) + (4 + MAX_CACHED_RESPONSE_LEN) + 8 + 1
+            + RESPONSE_META_SPACE,
+        seeds = [b"cache", cache_key.as_ref()],
+        bump
+    )]
+    pub cache: Account<'info, ResultCache>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitOracleAccount<'info> {
+    #[account(
+        init,
+        payer = oracle,
+        space = 8 + 32 + 4 + 8 + 1,
+        seeds = [b"oracle", oracle.key().as_ref()],
+        bump
+    )]
+    pub oracle_account: Account<'info, OracleAccount>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitEventCounter<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 8 + 1,
+        seeds = [b"event_counter"],
+        bump
+    )]
+    pub event_counter: Account<'info, GlobalCounter>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 4
+            + (4 + MAX_FORBIDDEN_SUBSTRINGS * (4 + MAX_FORBIDDEN_SUBSTRING_LEN))
+            + 32
+            + 2
+            + (4 + 32 * MAX_BLACKLIST)
+            + 1
+            + (4 + MAX_PROVIDER_STATUSES
+                * ((4 + 64) + (1 + 8) + (4 + 32 * PROVIDER_DEGRADED_QUORUM) + 8))
+            + 1,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, GlobalConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, GlobalConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReportProvider<'info> {
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, GlobalConfig>,
+    #[account(seeds = [b"registry"], bump = registry.bump)]
+    pub registry: Account<'info, OracleRegistry>,
+    pub oracle: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(response: Vec<u8>)]
+pub struct FulfillRequest<'info> {
+    #[account(
+        mut,
+        realloc = stored_response_space(&request, response.len()),
+        realloc::payer = oracle,
+        realloc::zero = false
+    )]
+    pub request: Account<'info, LLMRequest>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    /// CHECK: Validated against request.caller_program
+    pub callback_program: AccountInfo<'info>,
+    /// CHECK: Instructions sysvar, required when the request needs an attestation
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+    /// CHECK: Global config PDA, may be uninitialized; read via load_config
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+    /// CHECK: Must match config.treasury when a protocol fee is configured
+    #[account(mut)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        seeds = [b"cache", request.cache_key.as_ref()],
+        bump = result_cache.bump
+    )]
+    pub result_cache: Option<Account<'info, ResultCache>>,
+    pub system_program: Program<'info, System>,
+    #[account(mut, seeds = [b"event_counter"], bump = event_counter.bump)]
+    pub event_counter: Account<'info, GlobalCounter>,
+}
+
+#[derive(Accounts)]
+#[instruction(response: Vec<u8>, response_metadata: ResponseMeta)]
+pub struct FulfillNotifyOnly<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    /// CHECK: Validated against request.caller_program
+    pub callback_program: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = oracle,
+        space = 8 + 32 + 4 + response.len()
+            + 4 + response_metadata.model_id.len()
+            + 4 + response_metadata.finish_reason.len()
+            + 32,
+        seeds = [b"response", request.key().as_ref()],
+        bump
+    )]
+    pub response_buffer: Account<'info, ResponseBuffer>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Global config PDA, may be uninitialized; read via load_config
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+    /// CHECK: Must match config.treasury when a protocol fee is configured
+    #[account(mut)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    #[account(mut, seeds = [b"event_counter"], bump = event_counter.bump)]
+    pub event_counter: Account<'info, GlobalCounter>,
+}
+
+#[derive(Accounts)]
+#[instruction(total_len: u32)]
+pub struct InitFulfillment<'info> {
+    pub request: Account<'info, LLMRequest>,
+    #[account(
+        init,
+        payer = oracle,
+        space = 8 + 32 + 32 + 4 + 2 + 2 + (4 + total_len as usize) + 1,
+        seeds = [b"fulfillment", request.key().as_ref(), oracle.key().as_ref()],
+        bump
+    )]
+    pub buffer: Account<'info, FulfillmentBuffer>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AppendResponseChunk<'info> {
+    #[account(
+        mut,
+        seeds = [b"fulfillment", buffer.request.as_ref(), oracle.key().as_ref()],
+        bump = buffer.bump
+    )]
+    pub buffer: Account<'info, FulfillmentBuffer>,
+    pub oracle: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeFulfillment<'info> {
+    #[account(
+        mut,
+        realloc = stored_response_space(&request, buffer.data.len()),
+        realloc::payer = oracle,
+        realloc::zero = false
+    )]
+    pub request: Account<'info, LLMRequest>,
+    #[account(
+        mut,
+        close = oracle,
+        seeds = [b"fulfillment", request.key().as_ref(), oracle.key().as_ref()],
+        bump = buffer.bump
+    )]
+    pub buffer: Account<'info, FulfillmentBuffer>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    /// CHECK: Validated against request.caller_program
+    pub callback_program: AccountInfo<'info>,
+    /// CHECK: Global config PDA, may be uninitialized; read via load_config
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+    /// CHECK: Must match config.treasury when a protocol fee is configured
+    #[account(mut)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+    #[account(mut, seeds = [b"event_counter"], bump = event_counter.bump)]
+    pub event_counter: Account<'info, GlobalCounter>,
+}
+
+#[derive(Accounts)]
+pub struct FulfillBatch<'info> {
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    /// CHECK: Validated against each request's caller_program
+    pub callback_program: AccountInfo<'info>,
+    /// CHECK: Global config PDA, may be uninitialized; read via load_config
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+    /// CHECK: Must match config.treasury when a protocol fee is configured
+    #[account(mut)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    #[account(mut, seeds = [b"event_counter"], bump = event_counter.bump)]
+    pub event_counter: Account<'info, GlobalCounter>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReward<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AuthorizeCallbackRedirect<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcknowledgeDelivery<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    pub acknowledger: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReorderCallbackAccounts<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReplaceCallbackAccount<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireRequest<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseExpiredRequest<'info> {
+    #[account(mut, close = payer)]
+    pub request: Account<'info, LLMRequest>,
+    /// CHECK: Receives the refund; must be the request's original payer
     #[account(mut)]
+    pub payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseFulfilledRequest<'info> {
+    #[account(mut, close = payer)]
     pub request: Account<'info, LLMRequest>,
     #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseResponseBuffer<'info> {
+    #[account(
+        mut,
+        close = oracle,
+        has_one = oracle @ ErrorCode::Unauthorized,
+        seeds = [b"response", request.key().as_ref()],
+        bump
+    )]
+    pub response_buffer: Account<'info, ResponseBuffer>,
+    /// CHECK: Address of the buffer's request; must already be closed
+    #[account(address = response_buffer.request)]
+    pub request: UncheckedAccount<'info>,
+    #[account(mut)]
     pub oracle: Signer<'info>,
-    /// CHECK: Validated against request.caller_program
-    pub callback_program: AccountInfo<'info>,
 }
 
-#[account]
-pub struct LLMRequest {
-    pub id: String,
-    pub caller_program: Pubkey,
-    pub provider: String,
-    pub model_id: String,
-    pub callback_accounts: Vec<Pubkey>,
-    pub callback_writable: Vec<bool>,
-    pub status: RequestStatus,
-    pub created_at: i64,
-    pub min_votes: u8,
-    pub approval_threshold: u8,
-    pub votes: Vec<OracleVote>,
-    pub winning_hash: Option<[u8; 32]>,
-    pub total_votes_cast: u8,
-}
+#[derive(Accounts)]
+pub struct SwitchToFallback<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRequest<'info> {
+    #[account(mut, close = canceller)]
+    pub request: Account<'info, LLMRequest>,
+    #[account(mut)]
+    pub canceller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRequestPaused<'info> {
+    #[account(mut)]
+    pub request: Account<'info, LLMRequest>,
+    pub authority: Signer<'info>,
+    /// CHECK: Global config PDA, may be uninitialized; read via load_config
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ViewRequest<'info> {
+    pub request: Account<'info, LLMRequest>,
+}
+
+#[account]
+pub struct LLMRequest {
+    pub id: String,
+    pub caller_program: Pubkey,
+    pub provider: String,
+    pub model_id: String,
+    pub callback_accounts: Vec<Pubkey>,
+    pub callback_writable: Vec<bool>,
+    pub status: RequestStatus,
+    pub created_at: i64,
+    /// Oracles that must agree on the winning hash, counted by head even
+    /// under `VoteWeighting::Stake`.
+    pub min_votes: u8,
+    /// Minimum percentage (1-100) of cast votes the leading hash needs under
+    /// `ApprovalKind::Threshold`. With `RoundingMode::Exact` this means
+    /// `vote_count * 100 >= approval_threshold * total_votes`.
+    pub approval_threshold: u8,
+    pub votes: Vec<OracleVote>,
+    pub winning_hash: Option<[u8; 32]>,
+    pub total_votes_cast: u16,
+    pub tie_break: TieBreak,
+    pub payer: Pubkey,
+    pub callback_program_override: Option<Pubkey>,
+    pub require_content_entropy: bool,
+    pub approval_kind: ApprovalKind,
+    pub require_attestation: bool,
+    pub reward_lamports: u64,
+    pub fulfiller_bonus_bps: u16,
+    pub fulfilled_by: Option<Pubkey>,
+    pub conversation_hash: [u8; 32],
+    pub consumer_cooldown_seconds: i64,
+    pub expires_at: Option<i64>,
+    pub paused: bool,
+    pub normalize: NormalizeMode,
+    pub response_kind: ResponseKind,
+    pub notify_only: bool,
+    pub reward_mode: RewardMode,
+    pub peak_vote_count: u16,
+    /// X25519 public key the response must be encrypted to. The response is then
+    /// `ephemeral_pubkey || XChaCha20-Poly1305(plaintext)`, where the ephemeral
+    /// secret is `sha256("coolrouter-encrypt" || request_id || plaintext)` and the
+    /// nonce is the first 24 bytes of `sha256(request_id)`, so honest oracles
+    /// produce identical ciphertext and still agree on the response hash.
+    pub encrypt_to: Option<[u8; 32]>,
+    /// Quorum: distinct oracles, agreeing or not, that must have voted before any
+    /// hash can win. `create_request` defaults it to `min_votes`.
+    pub min_participation: u8,
+    pub delivery_acknowledged: bool,
+    pub acknowledged_at: Option<i64>,
+    pub fallback_model_id: Option<String>,
+    pub used_fallback: bool,
+    pub fee_per_response_byte: u64,
+    pub declined: Vec<Pubkey>,
+    pub trusted_oracle: Option<Pubkey>,
+    pub keeper_reward_lamports: u64,
+    pub pow_difficulty: u8,
+    pub created_slot: u64,
+    pub expires_at_slot: Option<u64>,
+    pub voting_completed_at: Option<i64>,
+    pub fulfilled_at: Option<i64>,
+    pub allow_cache: bool,
+    pub allowed_oracles: Option<Vec<Pubkey>>,
+    pub detect_format: bool,
+    pub detected_format: u8,
+    pub state_log: Vec<StateTransition>,
+    pub threshold_rounding: RoundingMode,
+    pub response_constraint: Option<ResponseConstraint>,
+    /// Votes the leading hash must be ahead of the runner-up by before
+    /// voting completes, counted by head even under `VoteWeighting::Stake`.
+    /// Zero keeps completion purely threshold-based.
+    pub early_completion_margin: u8,
+    pub store_response_onchain: bool,
+    pub vote_weighting: VoteWeighting,
+    /// Seconds after `voting_completed_at` during which a registered oracle
+    /// may dispute the result and fulfillment is refused. Zero disables it.
+    pub challenge_period_seconds: i64,
+    pub challenger: Option<Pubkey>,
+    /// Appends every hash's tally, losers included, to the callback payload.
+    pub rich_distribution_callback: bool,
+    /// Bumped whenever the votes are reset so relayed vote signatures from an
+    /// earlier round cannot be replayed.
+    pub vote_epoch: u64,
+    /// Extra provider/model pairs the prompt is sent to alongside `provider`
+    /// and `model_id`. Votes from every target count toward one consensus.
+    pub providers: Vec<ProviderTarget>,
+    pub multi_winner_policy: MultiWinnerPolicy,
+    pub generation_params: Option<GenerationParams>,
+    /// Seconds after the first vote that voting stays open. Zero leaves
+    /// voting open until the request expires.
+    pub voting_window_seconds: i64,
+    pub voting_deadline: Option<i64>,
+    /// Set together with `winning_hash` and never cleared; see `set_winning_hash`.
+    pub winning_hash_locked: bool,
+    /// Consumer instruction `fulfill_request` calls, `llm_callback` unless the
+    /// request named another.
+    pub callback_ix_name: String,
+    /// Seed of the result cache PDA, from `result_cache_key`.
+    pub cache_key: [u8; 32],
+    /// Must stay the last field: `fulfill_request` grows the account past
+    /// `LLM_REQUEST_SPACE` to hold it.
+    pub stored_response: Option<Vec<u8>>,
+}
+
+#[account]
+pub struct FulfillmentBuffer {
+    pub request: Pubkey,
+    pub oracle: Pubkey,
+    pub total_len: u32,
+    pub total_chunks: u16,
+    pub next_chunk: u16,
+    pub data: Vec<u8>,
+    pub bump: u8,
+}
+
+#[account]
+pub struct ResponseBuffer {
+    pub request: Pubkey,
+    pub response: Vec<u8>,
+    pub metadata: ResponseMeta,
+    pub oracle: Pubkey,
+}
+
+#[account]
+pub struct GlobalConfig {
+    pub admin: Pubkey,
+    pub daily_vote_cap: u32,
+    pub forbidden_substrings: Vec<String>,
+    pub treasury: Pubkey,
+    pub protocol_fee_bps: u16,
+    pub blacklist: Vec<Pubkey>,
+    /// Halts `create_request` and voting; fulfillment stays open so in-flight
+    /// requests can drain.
+    pub paused: bool,
+    pub provider_statuses: Vec<ProviderStatus>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProviderStatus {
+    pub provider: String,
+    pub degraded_at: Option<i64>,
+    pub reporters: Vec<Pubkey>,
+    pub first_report_at: i64,
+}
+
+#[account]
+pub struct ResultCache {
+    pub cache_key: [u8; 32],
+    pub model_id: String,
+    pub response: Vec<u8>,
+    pub cached_at: i64,
+    pub bump: u8,
+    /// Metadata the cached response was voted with, needed to rebuild its hash.
+    pub metadata: ResponseMeta,
+}
+
+#[account]
+pub struct OracleRegistry {
+    pub authority: Pubkey,
+    pub min_stake: u64,
+    pub deregistration_cooldown_seconds: i64,
+    pub oracles: Vec<RegisteredOracle>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RegisteredOracle {
+    pub oracle: Pubkey,
+    pub stake: u64,
+    pub deregistering_at: Option<i64>,
+}
+
+#[account]
+pub struct StakeVault {
+    pub bump: u8,
+}
+
+/// Sequence shared by every request's lifecycle events; see `next_event_seq`.
+#[account]
+pub struct GlobalCounter {
+    pub value: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct OracleAccount {
+    pub oracle: Pubkey,
+    pub votes_today: u32,
+    pub day_bucket: i64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct OracleConsumerCooldown {
+    pub oracle: Pubkey,
+    pub caller_program: Pubkey,
+    pub last_vote_at: i64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OracleVote {
+    pub oracle: Pubkey,
+    pub response_hash: [u8; 32],
+    pub voted_at: i64,
+    pub reward_claimed: bool,
+    /// 1 for unweighted requests, otherwise the oracle's registry stake at
+    /// vote time.
+    pub weight: u64,
+    /// 0 for the request's own `provider`/`model_id`, otherwise one past the
+    /// index into `providers`.
+    pub provider_index: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OracleAttestation {
+    pub oracle: Pubkey,
+    pub signature: [u8; 64],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct HashTally {
+    pub response_hash: [u8; 32],
+    pub count: u16,
+    pub weight: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchFulfillOutcome {
+    pub request: Pubkey,
+    pub fulfilled: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum RequestStatus {
+    Pending,
+    VotingCompleted,
+    Fulfilled,
+    Cancelled,
+    Expired,
+    Disputed,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OracleBatchRegistration {
+    pub added: u8,
+    pub skipped: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct StateTransition {
+    pub status: RequestStatus,
+    pub at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RequestStatusView {
+    pub status: RequestStatus,
+    pub min_votes: u8,
+    pub total_votes_cast: u16,
+    pub peak_vote_count: u16,
+    pub winning_hash: Option<[u8; 32]>,
+    pub winning_hash_prefix: Option<u32>,
+    pub expires_at: Option<i64>,
+    pub expires_at_slot: Option<u64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RequestSummary {
+    pub status: RequestStatus,
+    pub total_votes_cast: u16,
+    pub winning_hash: Option<[u8; 32]>,
+    pub votes_for_leader: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RequestTimeline {
+    pub created_at: i64,
+    pub first_vote_at: Option<i64>,
+    pub voting_deadline: Option<i64>,
+    pub voting_completed_at: Option<i64>,
+    pub fulfilled_at: Option<i64>,
+    pub expires_at: Option<i64>,
+}
+
+/// Cheap shape check applied to the response before delivery. `OneOf`
+/// matches the exact bytes of one option; `NumericRange` accepts a plain
+/// decimal integer within the inclusive bounds.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub enum ResponseConstraint {
+    OneOf(Vec<String>),
+    NumericRange(u64, u64),
+}
+
+/// How the vote percentage is rounded before it is compared against
+/// `approval_threshold`. `Floor` and `Exact` agree for whole-percent
+/// thresholds; `Ceil` lets a share that rounds up to the threshold pass.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub enum RoundingMode {
+    Floor,
+    Ceil,
+    Exact,
+}
+
+/// `Stake` weighs each vote by the oracle's stake in lamports for the
+/// approval rule only; `min_votes` and `early_completion_margin` stay head
+/// counts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum VoteWeighting {
+    Unweighted,
+    Stake,
+}
+
+/// How voting completes when more than one hash meets `min_votes` and the
+/// approval rule at once.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum MultiWinnerPolicy {
+    FirstToThreshold,
+    HighestCount,
+    RejectAmbiguous,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum TieBreak {
+    Lexicographic,
+    EarliestFirstVote,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum RewardMode {
+    WinnersOnly,
+    TopCluster,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum ResponseKind {
+    Text,
+    ToolCall,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum NormalizeMode {
+    Raw,
+    TrimTrailingWhitespace,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum ApprovalKind {
+    Threshold,
+    StrictMajority,
+    Plurality,
+}
+
+/// Sampling settings forwarded to the oracles. `temperature` and `top_p` are
+/// fixed-point in thousandths, so 2000 is 2.0 and 1000 is 1.0.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct GenerationParams {
+    pub temperature: u16,
+    pub max_tokens: u32,
+    pub top_p: u16,
+    pub seed: Option<u64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProviderTarget {
+    pub provider: String,
+    pub model_id: String,
+}
+
+/// Provider-reported details voted on together with the response.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ResponseMeta {
+    pub model_id: String,
+    pub finish_reason: String,
+}
+
+/// Everything `create_request` takes besides the request ID, which stays a
+/// separate argument because it seeds the request PDA.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreateRequestParams {
+    pub provider: String,
+    pub model_id: String,
+    pub messages: Vec<Message>,
+    pub min_votes: u8,
+    pub approval_threshold: u8,
+    pub tie_break: TieBreak,
+    pub require_content_entropy: bool,
+    pub approval_kind: ApprovalKind,
+    pub require_attestation: bool,
+    pub reward_lamports: u64,
+    pub fulfiller_bonus_bps: u16,
+    pub consumer_cooldown_seconds: i64,
+    pub expiry_seconds: i64,
+    pub normalize: NormalizeMode,
+    pub response_kind: ResponseKind,
+    pub notify_only: bool,
+    pub reward_mode: RewardMode,
+    pub encrypt_to: Option<[u8; 32]>,
+    pub min_participation: u8,
+    pub fallback_model_id: Option<String>,
+    pub fee_per_response_byte: u64,
+    pub trusted_oracle: Option<Pubkey>,
+    pub keeper_reward_lamports: u64,
+    pub pow_difficulty: u8,
+    pub expires_at_slot: u64,
+    pub sanitize_content: bool,
+    pub allow_cache: bool,
+    pub cache_ttl_seconds: i64,
+    pub allowed_oracles: Option<Vec<Pubkey>>,
+    pub detect_format: bool,
+    pub threshold_rounding: RoundingMode,
+    pub response_constraint: Option<ResponseConstraint>,
+    pub early_completion_margin: u8,
+    pub store_response_onchain: bool,
+    pub vote_weighting: VoteWeighting,
+    pub challenge_period_seconds: i64,
+    pub rich_distribution_callback: bool,
+    pub providers: Vec<ProviderTarget>,
+    pub multi_winner_policy: MultiWinnerPolicy,
+    pub generation_params: Option<GenerationParams>,
+    pub voting_window_seconds: i64,
+    pub callback_ix_name: String,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+#[event]
+pub struct RequestCreated {
+    pub seq: u64,
+    pub request_id: String,
+    pub caller_program: Pubkey,
+    pub provider: String,
+    pub model_id: String,
+    pub messages: Vec<Message>,
+    pub min_votes: u8,
+    pub approval_threshold: u8,
+    pub tie_break: TieBreak,
+    pub approval_kind: ApprovalKind,
+    pub reward_lamports: u64,
+    pub conversation_hash: [u8; 32],
+    pub response_kind: ResponseKind,
+    pub encrypt_to: Option<[u8; 32]>,
+    pub allowed_oracles_len: Option<u8>,
+    pub providers: Vec<ProviderTarget>,
+    pub generation_params: Option<GenerationParams>,
+}
+
+#[event]
+pub struct VotingCompleted {
+    pub seq: u64,
+    pub request_id: String,
+    pub winning_hash: [u8; 32],
+    pub winning_hash_prefix: u32,
+    pub vote_count: u16,
+    pub total_votes: u16,
+    pub weight: u64,
+    /// Per-hash breakdown at completion, in first-vote order; same shape as `get_tally`.
+    pub tallies: Vec<HashTally>,
+    pub approval_threshold: u8,
+}
+
+#[event]
+pub struct BatchFulfillResult {
+    pub callback_program: Pubkey,
+    pub outcomes: Vec<BatchFulfillOutcome>,
+}
+
+#[event]
+pub struct UnauthorizedVoteAttempt {
+    pub oracle: Pubkey,
+    pub request_id: String,
+    pub reason: u8,
+}
+
+#[event]
+pub struct RewardClaimed {
+    pub request_id: String,
+    pub oracle: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CallbackRedirectAuthorized {
+    pub request_id: String,
+    pub original_program: Pubkey,
+    pub callback_program: Pubkey,
+}
+
+#[event]
+pub struct DisputeRaised {
+    pub request_id: String,
+    pub challenger: Pubkey,
+    pub winning_hash: Option<[u8; 32]>,
+}
+
+#[event]
+pub struct TrustedOracleCompletion {
+    pub request_id: String,
+    pub oracle: Pubkey,
+    pub winning_hash: [u8; 32],
+}
+
+#[event]
+pub struct RequestDeclined {
+    pub request_id: String,
+    pub oracle: Pubkey,
+}
+
+#[event]
+pub struct CacheHit {
+    pub request_id: String,
+    pub conversation_hash: [u8; 32],
+    pub winning_hash: [u8; 32],
+    pub cached_at: i64,
+}
+
+#[event]
+pub struct MinStakeUpdated {
+    pub min_stake: u64,
+}
+
+#[event]
+pub struct OraclesBatchRegistered {
+    pub added: u8,
+    pub skipped: u8,
+}
+
+#[event]
+pub struct OracleRegistered {
+    pub oracle: Pubkey,
+    pub stake: u64,
+}
+
+#[event]
+pub struct OracleDeregistrationStarted {
+    pub oracle: Pubkey,
+    pub withdrawable_at: i64,
+}
+
+#[event]
+pub struct StakeWithdrawn {
+    pub oracle: Pubkey,
+    pub stake: u64,
+}
+
+#[event]
+pub struct OracleBlacklistUpdated {
+    pub oracle: Pubkey,
+    pub blacklisted: bool,
+}
+
+#[event]
+pub struct ProtocolFeeCollected {
+    pub request_id: String,
+    pub treasury: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ResponseFeePaid {
+    pub request_id: String,
+    pub fulfiller: Pubkey,
+    pub fee: u64,
+}
+
+#[event]
+pub struct FallbackActivated {
+    pub request_id: String,
+    pub fallback_model_id: String,
+}
+
+#[event]
+pub struct DeliveryAcknowledged {
+    pub request_id: String,
+    pub acknowledger: Pubkey,
+    pub acknowledged_at: i64,
+}
+
+#[event]
+pub struct DailyVoteCapUpdated {
+    pub daily_vote_cap: u32,
+}
+
+#[event]
+pub struct CallbackAccountsReordered {
+    pub request_id: String,
+    pub new_order: Vec<u8>,
+}
+
+#[event]
+pub struct CallbackAccountReplaced {
+    pub request_id: String,
+    pub index: u8,
+    pub old_key: Pubkey,
+    pub new_key: Pubkey,
+}
+
+#[event]
+pub struct RequestExpired {
+    pub request_id: String,
+    pub peak_vote_count: u16,
+    pub keeper: Pubkey,
+    pub keeper_reward: u64,
+}
+
+#[event]
+pub struct ExpiredRequestClosed {
+    pub request_id: String,
+    pub payer: Pubkey,
+    pub refunded_lamports: u64,
+}
+
+#[event]
+pub struct FulfilledRequestClosed {
+    pub request_id: String,
+    pub payer: Pubkey,
+    pub status: RequestStatus,
+    pub refunded_lamports: u64,
+}
+
+#[event]
+pub struct RequestCancelled {
+    pub request_id: String,
+    pub forced: bool,
+    pub compensation_lamports: u64,
+    /// Rent plus remaining escrow returned to the payer when the account closes.
+    pub refunded_lamports: u64,
+}
+
+#[event]
+pub struct RelayedVoteSubmitted {
+    pub request_id: String,
+    pub oracle: Pubkey,
+    pub relayer: Pubkey,
+}
+
+#[event]
+pub struct ProviderStatusChanged {
+    pub provider: String,
+    pub degraded: bool,
+}
+
+#[event]
+pub struct RouterPauseChanged {
+    pub paused: bool,
+}
+
+#[event]
+pub struct RequestPaused {
+    pub request_id: String,
+    pub reason: u8,
+}
+
+#[event]
+pub struct RequestResumed {
+    pub request_id: String,
+    pub reason: u8,
+}
+
+#[event]
+pub struct RequestFulfilled {
+    pub seq: u64,
+    pub request_id: String,
+    pub response_length: u64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Too many callback accounts (max 32)")]
+    TooManyAccounts,
+    #[msg("Account mismatch in remaining accounts")]
+    AccountMismatch,
+    #[msg("Voting is closed or request already fulfilled")]
+    VotingClosed,
+    #[msg("Callback program does not match")]
+    CallbackProgramMismatch,
+    #[msg("Account count mismatch")]
+    AccountCountMismatch,
+    #[msg("Provider exceeds 64 characters")]
+    ProviderTooLong,
+    #[msg("Model ID exceeds 64 characters")]
+    ModelIdTooLong,
+    #[msg("Too many messages (max 50)")]
+    TooManyMessages,
+    #[msg("Minimum votes must be greater than 0")]
+    InvalidMinVotes,
+    #[msg("Approval threshold must be between 1 and 100")]
+    InvalidApprovalThreshold,
+    #[msg("Oracle has already voted")]
+    OracleAlreadyVoted,
+    #[msg("Too many votes submitted (max 32 oracles)")]
+    TooManyVotes,
+    #[msg("Voting has not been completed yet")]
+    VotingNotCompleted,
+    #[msg("No winning hash available")]
+    NoWinningHash,
+    #[msg("Response hash does not match winning hash")]
+    ResponseHashMismatch,
+    #[msg("Unauthorized: signer is not the request payer")]
+    Unauthorized,
+    #[msg("Request has already been fulfilled")]
+    RequestAlreadyFulfilled,
+    #[msg("Response content entropy is too low")]
+    ResponseEntropyTooLow,
+    #[msg("Batch must contain between 1 and 4 requests")]
+    InvalidBatchSize,
+    #[msg("Request requires an ed25519 attestation from the fulfilling oracle")]
+    MissingAttestation,
+    #[msg("Oracle attestation does not match the fulfilled response")]
+    InvalidAttestation,
+    #[msg("Fulfiller bonus must not exceed 10000 bps of the reward pool")]
+    InvalidRewardSplit,
+    #[msg("Request has not been fulfilled yet")]
+    RequestNotFulfilled,
+    #[msg("Oracle did not vote for the winning hash")]
+    NotWinningOracle,
+    #[msg("Reward has already been claimed")]
+    RewardAlreadyClaimed,
+    #[msg("Request is not pending")]
+    RequestNotPending,
+    #[msg("Request cannot be cancelled after votes have been cast")]
+    CannotCancelAfterVoting,
+    #[msg("Consumer cooldown must not be negative")]
+    InvalidCooldown,
+    #[msg("Oracle consumer cooldown account is required for this request")]
+    MissingCooldownAccount,
+    #[msg("Oracle is still in its cooldown for this consumer")]
+    OracleConsumerCooldown,
+    #[msg("Expiry must not be negative")]
+    InvalidExpiry,
+    #[msg("Request has no expiry configured")]
+    NoExpiryConfigured,
+    #[msg("Request is paused")]
+    RequestPaused,
+    #[msg("Request is not paused")]
+    RequestNotPaused,
+    #[msg("Callback program cannot be the router, the system program or a sysvar")]
+    InvalidCallbackProgram,
+    #[msg("Request uses notify-only delivery")]
+    NotifyOnlyRequest,
+    #[msg("Request does not use notify-only delivery")]
+    NotNotifyOnlyRequest,
+    #[msg("RequestCreated event exceeds the log data limit; shorten the messages")]
+    EventTooLarge,
+    #[msg("New order is not a permutation of the callback accounts")]
+    InvalidPermutation,
+    #[msg("Global config account could not be read")]
+    InvalidConfigAccount,
+    #[msg("Oracle account required when a daily vote cap is configured")]
+    MissingOracleAccount,
+    #[msg("Oracle has reached its daily vote limit")]
+    DailyVoteLimitReached,
+    #[msg("Encrypted responses cannot be normalized")]
+    NormalizeEncryptedResponse,
+    #[msg("Minimum participation exceeds the maximum number of oracles")]
+    InvalidMinParticipation,
+    #[msg("Delivery has already been acknowledged")]
+    DeliveryAlreadyAcknowledged,
+    #[msg("No fallback model configured for this request")]
+    NoFallbackModel,
+    #[msg("Request has neither expired nor diverged")]
+    ConsensusStillPossible,
+    #[msg("Escrowed reward cannot cover the response fee")]
+    InsufficientFeeForResponse,
+    #[msg("Oracle has already declined this request")]
+    OracleAlreadyDeclined,
+    #[msg("Maximum number of declines reached")]
+    TooManyDeclines,
+    #[msg("Trusted oracle must be an active oracle in the registry")]
+    TrustedOracleNotRegistered,
+    #[msg("Keeper reward cannot exceed the escrowed reward")]
+    InvalidKeeperReward,
+    #[msg("Request cannot be expired in its current status")]
+    RequestNotExpirable,
+    #[msg("Request has not reached its expiry")]
+    RequestNotExpired,
+    #[msg("Proof-of-work difficulty exceeds the maximum")]
+    InvalidPowDifficulty,
+    #[msg("Vote nonce does not meet the proof-of-work difficulty")]
+    InsufficientProofOfWork,
+    #[msg("Configure either timestamp or slot expiry, not both")]
+    ConflictingExpiry,
+    #[msg("Request has expired")]
+    RequestExpired,
+    #[msg("Global config account is required and must be initialized")]
+    MissingConfigAccount,
+    #[msg("Forbidden substring list is invalid")]
+    InvalidForbiddenSubstrings,
+    #[msg("Message content contains a forbidden substring")]
+    SuspiciousContent,
+    #[msg("Protocol fee exceeds 10000 basis points")]
+    InvalidProtocolFee,
+    #[msg("Treasury account does not match the configured treasury")]
+    InvalidTreasury,
+    #[msg("Oracle is blacklisted")]
+    OracleBlacklisted,
+    #[msg("Oracle is not blacklisted")]
+    OracleNotBlacklisted,
+    #[msg("Blacklist is full")]
+    BlacklistFull,
+    #[msg("Oracle is not registered with sufficient stake")]
+    OracleNotRegistered,
+    #[msg("Oracle is already registered")]
+    OracleAlreadyRegistered,
+    #[msg("Oracle registry is full")]
+    RegistryFull,
+    #[msg("Stake is below the registry minimum")]
+    InsufficientStake,
+    #[msg("Oracle deregistration is already in progress")]
+    OracleAlreadyDeregistering,
+    #[msg("Oracle has not started deregistration")]
+    OracleNotDeregistering,
+    #[msg("Stake is still in its deregistration cooldown")]
+    StakeCooldownActive,
+    #[msg("Result cache account is missing or does not match the conversation")]
+    MissingCacheAccount,
+    #[msg("Oracle registry account is missing or is not the registry PDA")]
+    MissingRegistryAccount,
+    #[msg("Cache TTL cannot be negative")]
+    InvalidCacheTtl,
+    #[msg("Oracle is not on this request's allowlist")]
+    OracleNotAllowed,
+    #[msg("Too many allowed oracles (max 32)")]
+    TooManyAllowedOracles,
+    #[msg("Payer balance does not cover the request escrow")]
+    InsufficientPayerBalance,
+    #[msg("Response constraint is malformed or used with an encrypted response")]
+    InvalidResponseConstraint,
+    #[msg("Response does not satisfy the request's response constraint")]
+    ResponseConstraintViolated,
+    #[msg("Response exceeds the on-chain storage limit (max 1020 bytes)")]
+    StoredResponseTooLarge,
+    #[msg("Request has no stored response")]
+    NoStoredResponse,
+    #[msg("Requests that store the response on-chain cannot be batch fulfilled")]
+    StoredResponseNotBatchable,
+    #[msg("Chunk layout is invalid or exceeds the declared length (max 8192 bytes)")]
+    InvalidChunk,
+    #[msg("Chunk index must be the next expected chunk")]
+    ChunkOutOfOrder,
+    #[msg("Not all response chunks have been submitted")]
+    ResponseIncomplete,
+    #[msg("Challenge period cannot be negative")]
+    InvalidChallengePeriod,
+    #[msg("Challenge window is still open")]
+    ChallengeWindowOpen,
+    #[msg("Challenge window has closed")]
+    ChallengeWindowClosed,
+    #[msg("Router is paused")]
+    RouterPaused,
+    #[msg("Callback account index is out of range")]
+    InvalidCallbackIndex,
+    #[msg("Request must include at least one message")]
+    EmptyMessages,
+    #[msg("Message role must be system, user or assistant")]
+    InvalidRole,
+    #[msg("Message content too long (max 1024 bytes)")]
+    MessageContentTooLong,
+    #[msg("Too many providers (max 4)")]
+    TooManyProviders,
+    #[msg("Provider index does not match a request target")]
+    InvalidProviderIndex,
+    #[msg("Generation params out of range (temperature <= 2000, top_p <= 1000, max_tokens > 0)")]
+    InvalidGenerationParams,
+    #[msg("Requested provider is flagged as degraded")]
+    ProviderUnavailable,
+    #[msg("Provider status list is full (max 8)")]
+    ProviderStatusesFull,
+    #[msg("Voting window cannot be negative")]
+    InvalidVotingWindow,
+    #[msg("Voting window has closed")]
+    VotingWindowClosed,
+    #[msg("Winning hash is locked and cannot be changed")]
+    WinningHashLocked,
+    #[msg("Callback name must be 1-32 lowercase letters, digits or underscores")]
+    InvalidCallbackIxName,
+    #[msg("Content type must be 0 (text), 1 (JSON) or 2 (binary)")]
+    InvalidContentType,
+    #[msg("Content type does not match the detected response format")]
+    ContentTypeMismatch,
+    #[msg("Vote counter overflowed")]
+    VoteCountOverflow,
+    #[msg("Finish reason exceeds 32 characters")]
+    FinishReasonTooLong,
+    #[msg("Only fulfilled, expired or cancelled requests can be closed")]
+    RequestNotClosable,
+    #[msg("Oracle rewards must be claimed or the claim window must pass before the request is closed")]
+    RewardsUnclaimed,
+    #[msg("Event sequence counter overflowed")]
+    EventSeqOverflow,
+    #[msg("Request must be closed before its response buffer")]
+    RequestStillOpen,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock_at(slot: u64, unix_timestamp: i64) -> Clock {
+        Clock {
+            slot,
+            unix_timestamp,
+            ..Clock::default()
+        }
+    }
+
+    #[test]
+    fn slot_deadline_expires_after_boundary() {
+        assert!(!deadline_passed(None, Some(100), &clock_at(99, 0)));
+        assert!(!deadline_passed(None, Some(100), &clock_at(100, 0)));
+        assert!(deadline_passed(None, Some(100), &clock_at(101, 0)));
+    }
+
+    #[test]
+    fn slot_deadline_ignores_timestamp() {
+        assert!(!deadline_passed(None, Some(100), &clock_at(99, i64::MAX)));
+    }
+
+    #[test]
+    fn no_deadline_never_expires() {
+        assert!(!deadline_passed(None, None, &clock_at(u64::MAX, i64::MAX)));
+    }
+
+    #[test]
+    fn detects_json_after_leading_whitespace() {
+        assert_eq!(detect_response_format(b"  {\"a\": 1}"), FORMAT_JSON);
+        assert_eq!(detect_response_format(b"[1, 2]"), FORMAT_JSON);
+    }
+
+    #[test]
+    fn detects_text_and_binary() {
+        assert_eq!(detect_response_format(b"hello"), FORMAT_TEXT);
+        assert_eq!(detect_response_format(b""), FORMAT_TEXT);
+        assert_eq!(detect_response_format(&[0xff, 0xfe]), FORMAT_BINARY);
+    }
+
+    /// Every field zeroed: empty strings and vectors, `None`, first variants.
+    fn blank_request() -> LLMRequest {
+        LLMRequest::deserialize(&mut &vec![0u8; LLM_REQUEST_SPACE][..]).unwrap()
+    }
+
+    fn event_counter() -> GlobalCounter {
+        GlobalCounter { value: 0, bump: 0 }
+    }
+
+    fn cast(
+        request: &mut LLMRequest,
+        counter: &mut GlobalCounter,
+        oracle: u8,
+        response_hash: u8,
+    ) -> Result<()> {
+        let oracle_key = Pubkey::new_from_array([oracle; 32]);
+        record_vote(request, counter, oracle_key, [response_hash; 32], 1, 0, oracle as i64)
+    }
+
+    fn vote(oracle: u8, response_hash: u8, voted_at: i64) -> OracleVote {
+        weighted_vote(oracle, response_hash, voted_at, 1)
+    }
+
+    /// Lays accounts out the way the runtime serializes program input, with
+    /// realloc headroom, so closing one in a test resizes real account data.
+    fn program_input(accounts: &[(Pubkey, Pubkey, u64, Vec<u8>)]) -> Vec<u64> {
+        use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+        
+        let mut input = (accounts.len() as u64).to_le_bytes().to_vec();
+        for (key, owner, lamports, data) in accounts {
+            input.extend_from_slice(&[u8::MAX, 1, 1, 0, 0, 0, 0, 0]);
+            input.extend_from_slice(key.as_ref());
+            input.extend_from_slice(owner.as_ref());
+            input.extend_from_slice(&lamports.to_le_bytes());
+            input.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            input.extend_from_slice(data);
+            input.resize((input.len() + MAX_PERMITTED_DATA_INCREASE).next_multiple_of(8), 0);
+            input.extend_from_slice(&u64::MAX.to_le_bytes());
+        }
+        input.extend_from_slice(&0u64.to_le_bytes());
+        input.extend_from_slice(crate::ID.as_ref());
+        
+        input
+            .chunks(8)
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    fn request_data(request: &LLMRequest) -> Vec<u8> {
+        let mut data = Vec::new();
+        request.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    fn weighted_vote(oracle: u8, response_hash: u8, voted_at: i64, weight: u64) -> OracleVote {
+        OracleVote {
+            oracle: Pubkey::new_from_array([oracle; 32]),
+            response_hash: [response_hash; 32],
+            voted_at,
+            reward_claimed: false,
+            weight,
+            provider_index: 0,
+        }
+    }
+
+    #[test]
+    fn lexicographic_tie_picks_smallest_hash() {
+        let votes = vec![vote(1, 9, 0), vote(2, 4, 1), vote(3, 9, 2), vote(4, 4, 3)];
+        
+        assert_eq!(leading_hash(&votes, &TieBreak::Lexicographic), Some(([4; 32], 2)));
+    }
+
+    #[test]
+    fn lexicographic_tie_ignores_submission_order() {
+        let mut votes = vec![vote(1, 9, 0), vote(2, 4, 1), vote(3, 9, 2), vote(4, 4, 3)];
+        let expected = leading_hash(&votes, &TieBreak::Lexicographic);
+        
+        votes.reverse();
+        assert_eq!(leading_hash(&votes, &TieBreak::Lexicographic), expected);
+        
+        votes.rotate_left(1);
+        assert_eq!(leading_hash(&votes, &TieBreak::Lexicographic), expected);
+    }
+
+    #[test]
+    fn plurality_only_approves_a_strict_leader() {
+        let mut request = blank_request();
+        request.approval_kind = ApprovalKind::Plurality;
+        request.votes = vec![vote(1, 7, 0), vote(2, 7, 1), vote(3, 7, 2), vote(4, 8, 3)];
+        
+        assert!(is_approved(&request, 3));
+        assert!(!is_approved(&request, 1));
+        
+        request.votes.extend([vote(5, 8, 4), vote(6, 8, 5)]);
+        assert!(!is_approved(&request, 3));
+    }
+
+    #[test]
+    fn two_of_three_against_67_percent() {
+        assert!(!threshold_met(2, 3, 67, &RoundingMode::Floor));
+        assert!(threshold_met(2, 3, 67, &RoundingMode::Ceil));
+        assert!(!threshold_met(2, 3, 67, &RoundingMode::Exact));
+        assert!(threshold_met(2, 3, 66, &RoundingMode::Exact));
+    }
+
+    #[test]
+    fn exact_percentages_pass_in_every_mode() {
+        for rounding in [RoundingMode::Floor, RoundingMode::Ceil, RoundingMode::Exact] {
+            assert!(threshold_met(3, 5, 60, &rounding));
+            assert!(!threshold_met(3, 5, 61, &rounding));
+        }
+    }
+
+    #[test]
+    fn exact_matches_truncating_division_at_boundaries() {
+        let truncating = |count: u64, total: u64, threshold: u64| (count * 100) / total >= threshold;
+        
+        for (count, total) in [(2u64, 3u64), (3, 5)] {
+            for threshold in 1..=100u8 {
+                assert_eq!(
+                    threshold_met(count, total, threshold, &RoundingMode::Exact),
+                    truncating(count, total, threshold as u64),
+                    "{}/{} at {}%",
+                    count,
+                    total,
+                    threshold
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn one_of_matches_exact_option_bytes() {
+        let constraint = ResponseConstraint::OneOf(vec!["yes".to_string(), "no".to_string()]);
+        
+        assert!(satisfies_constraint(&constraint, b"yes"));
+        assert!(!satisfies_constraint(&constraint, b"Yes"));
+        assert!(!satisfies_constraint(&constraint, b"yes "));
+    }
+
+    #[test]
+    fn numeric_range_is_inclusive_and_digits_only() {
+        let constraint = ResponseConstraint::NumericRange(10, 20);
+        
+        assert!(satisfies_constraint(&constraint, b"10"));
+        assert!(satisfies_constraint(&constraint, b"20"));
+        assert!(!satisfies_constraint(&constraint, b"21"));
+        assert!(!satisfies_constraint(&constraint, b"+15"));
+        assert!(!satisfies_constraint(&constraint, b""));
+    }
+
+    #[test]
+    fn runner_up_excludes_leader() {
+        let votes = vec![vote(1, 9, 0), vote(2, 9, 1), vote(3, 9, 2), vote(4, 4, 3)];
+        
+        assert_eq!(runner_up_votes(&votes, &[9; 32]), 1);
+        assert_eq!(runner_up_votes(&votes[..3], &[9; 32]), 0);
+    }
+
+    #[test]
+    fn unweighted_votes_count_heads() {
+        let votes = vec![vote(1, 9, 0), vote(2, 9, 1), vote(3, 4, 2)];
+        
+        assert_eq!(leading_hash(&votes, &TieBreak::Lexicographic), Some(([9; 32], 2)));
+        assert!(threshold_met(2, 3, 66, &RoundingMode::Exact));
+    }
+
+    #[test]
+    fn stake_weight_outvotes_head_count() {
+        let votes = vec![
+            weighted_vote(1, 9, 0, 100),
+            weighted_vote(2, 9, 1, 100),
+            weighted_vote(3, 4, 2, 1_000),
+        ];
+        
+        assert_eq!(leading_hash(&votes, &TieBreak::Lexicographic), Some(([4; 32], 1_000)));
+        assert_eq!(votes_for(&votes, &[4; 32]), 1);
+        assert!(threshold_met(1_000, 1_200, 83, &RoundingMode::Exact));
+        assert!(!threshold_met(1_000, 1_200, 84, &RoundingMode::Exact));
+    }
+
+    #[test]
+    fn tallies_match_internal_count() {
+        let votes = vec![
+            weighted_vote(1, 9, 0, 5),
+            weighted_vote(2, 4, 1, 3),
+            weighted_vote(3, 9, 2, 2),
+        ];
+        
+        let tallies = hash_tallies(&votes);
+        let counted = count_votes(&votes);
+        
+        assert_eq!(tallies.len(), counted.len());
+        for (tally, (hash, weight)) in tallies.iter().zip(counted.iter()) {
+            assert_eq!(tally.response_hash, *hash);
+            assert_eq!(tally.weight, *weight);
+            assert_eq!(tally.count, votes_for(&votes, hash));
+        }
+        assert_eq!(tallies[0].count, 2);
+        assert_eq!(tallies[0].weight, 7);
+    }
+
+    #[test]
+    fn challenge_window_closes_after_period() {
+        assert!(!challenge_window_open(0, Some(100), 100));
+        assert!(challenge_window_open(60, Some(100), 100));
+        assert!(challenge_window_open(60, Some(100), 159));
+        assert!(!challenge_window_open(60, Some(100), 160));
+    }
+
+    #[test]
+    fn hash_prefix_reads_leading_bytes() {
+        let mut hash = [0xff; 32];
+        hash[..4].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        
+        assert_eq!(hash_prefix(&hash), 0x1234_5678);
+    }
+
+    fn config(paused: bool) -> GlobalConfig {
+        GlobalConfig {
+            admin: Pubkey::new_from_array([1; 32]),
+            daily_vote_cap: 0,
+            forbidden_substrings: Vec::new(),
+            treasury: Pubkey::new_from_array([1; 32]),
+            protocol_fee_bps: 0,
+            blacklist: Vec::new(),
+            paused,
+            provider_statuses: Vec::new(),
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn payer_or_admin_can_pause_a_request() {
+        let mut request = blank_request();
+        request.payer = Pubkey::new_from_array([2; 32]);
+        let admin = Pubkey::new_from_array([1; 32]);
+        let stranger = Pubkey::new_from_array([3; 32]);
+        
+        assert!(authorize_pause(&request, None, &request.payer).is_ok());
+        assert!(authorize_pause(&request, Some(&config(false)), &admin).is_ok());
+        assert!(matches!(
+            authorize_pause(&request, None, &admin),
+            Err(ErrorCode::Unauthorized)
+        ));
+        assert!(matches!(
+            authorize_pause(&request, Some(&config(false)), &stranger),
+            Err(ErrorCode::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn uninitialized_config_never_pauses() {
+        assert!(ensure_not_paused(None).is_ok());
+    }
+
+    #[test]
+    fn pause_and_unpause_toggle_gate() {
+        let mut config = config(false);
+        assert!(ensure_not_paused(Some(&config)).is_ok());
+        
+        config.paused = true;
+        assert!(matches!(
+            ensure_not_paused(Some(&config)),
+            Err(ErrorCode::RouterPaused)
+        ));
+        
+        config.paused = false;
+        assert!(ensure_not_paused(Some(&config)).is_ok());
+    }
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn accepts_multi_message_conversation() {
+        let messages = vec![
+            message("system", "You are terse."),
+            message("user", "Capital of France?"),
+            message("assistant", "Paris."),
+            message("user", "And Italy?"),
+        ];
+        
+        assert!(validate_messages(&messages).is_ok());
+    }
+
+    #[test]
+    fn rejects_empty_messages() {
+        assert!(matches!(validate_messages(&[]), Err(ErrorCode::EmptyMessages)));
+    }
+
+    #[test]
+    fn rejects_unknown_role() {
+        let messages = vec![message("user", "hi"), message("tool", "result")];
+        
+        assert!(matches!(validate_messages(&messages), Err(ErrorCode::InvalidRole)));
+        assert!(matches!(
+            validate_messages(&[message("User", "hi")]),
+            Err(ErrorCode::InvalidRole)
+        ));
+    }
+
+    #[test]
+    fn rejects_overlong_content() {
+        let at_limit = "a".repeat(MAX_MESSAGE_CONTENT_LEN);
+        let over_limit = "a".repeat(MAX_MESSAGE_CONTENT_LEN + 1);
+        
+        assert!(validate_messages(&[message("user", &at_limit)]).is_ok());
+        assert!(matches!(
+            validate_messages(&[message("user", &over_limit)]),
+            Err(ErrorCode::MessageContentTooLong)
+        ));
+    }
+
+    #[test]
+    fn relayed_vote_message_binds_request_and_epoch() {
+        let request = Pubkey::new_from_array([7; 32]);
+        let other = Pubkey::new_from_array([8; 32]);
+        let message = relayed_vote_message(&request, 0, &[9; 32], 42, 0);
+        
+        assert_eq!(message.len(), 32 + 8 + 32 + 8 + 1);
+        assert_ne!(message, relayed_vote_message(&other, 0, &[9; 32], 42, 0));
+        assert_ne!(message, relayed_vote_message(&request, 1, &[9; 32], 42, 0));
+        assert_ne!(message, relayed_vote_message(&request, 0, &[9; 32], 43, 0));
+        assert_ne!(message, relayed_vote_message(&request, 0, &[9; 32], 42, 1));
+    }
+
+    fn two_qualifying_votes() -> Vec<OracleVote> {
+        vec![
+            vote(1, 4, 0),
+            vote(2, 4, 1),
+            vote(3, 9, 2),
+            vote(4, 9, 3),
+            vote(5, 9, 4),
+        ]
+    }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct OracleVote {
-    pub oracle: Pubkey,
-    pub response_hash: [u8; 32],
-}
+    #[test]
+    fn highest_count_completes_on_leader() {
+        let votes = two_qualifying_votes();
+        let policy = MultiWinnerPolicy::HighestCount;
+        
+        assert_eq!(
+            select_winner(&votes, &TieBreak::Lexicographic, &policy, 2, |_| true),
+            Some(([9; 32], 3))
+        );
+    }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
-pub enum RequestStatus {
-    Pending,
-    VotingCompleted,
-    Fulfilled,
-}
+    #[test]
+    fn first_to_threshold_completes_on_earliest_qualifier() {
+        let votes = two_qualifying_votes();
+        let policy = MultiWinnerPolicy::FirstToThreshold;
+        
+        assert_eq!(
+            select_winner(&votes, &TieBreak::Lexicographic, &policy, 2, |_| true),
+            Some(([4; 32], 2))
+        );
+    }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct Message {
-    pub role: String,
-    pub content: String,
-}
+    #[test]
+    fn reject_ambiguous_waits_for_single_qualifier() {
+        let votes = two_qualifying_votes();
+        let policy = MultiWinnerPolicy::RejectAmbiguous;
+        
+        assert_eq!(
+            select_winner(&votes, &TieBreak::Lexicographic, &policy, 2, |_| true),
+            None
+        );
+        assert_eq!(
+            select_winner(&votes, &TieBreak::Lexicographic, &policy, 3, |_| true),
+            Some(([9; 32], 3))
+        );
+    }
 
-#[event]
-pub struct RequestCreated {
-    pub request_id: String,
-    pub caller_program: Pubkey,
-    pub provider: String,
-    pub model_id: String,
-    pub messages: Vec<Message>,
-    pub min_votes: u8,
-    pub approval_threshold: u8,
-}
+    #[test]
+    fn unapproved_leader_never_completes() {
+        let votes = two_qualifying_votes();
+        
+        for policy in [
+            MultiWinnerPolicy::HighestCount,
+            MultiWinnerPolicy::FirstToThreshold,
+            MultiWinnerPolicy::RejectAmbiguous,
+        ] {
+            assert_eq!(
+                select_winner(&votes, &TieBreak::Lexicographic, &policy, 2, |weight| weight > 5),
+                None
+            );
+        }
+    }
 
-#[event]
-pub struct VotingCompleted {
-    pub request_id: String,
-    pub winning_hash: [u8; 32],
-    pub vote_count: u8,
-    pub total_votes: u8,
-}
+    #[test]
+    fn stake_weights_count_heads_for_min_votes() {
+        let stake = 5_000_000_000_000_000_000;
+        let votes = vec![
+            weighted_vote(1, 9, 0, stake),
+            weighted_vote(2, 9, 1, stake),
+            weighted_vote(3, 4, 2, 1),
+        ];
+        let policy = MultiWinnerPolicy::HighestCount;
+        let approved = |weight| threshold_met(weight, stake * 2 + 1, 66, &RoundingMode::Exact);
+        
+        assert_eq!(
+            select_winner(&votes, &TieBreak::Lexicographic, &policy, 2, approved),
+            Some(([9; 32], stake * 2))
+        );
+        assert_eq!(
+            select_winner(&votes, &TieBreak::Lexicographic, &policy, 3, approved),
+            None
+        );
+        assert_eq!(runner_up_votes(&votes, &[9; 32]), 1);
+    }
 
-#[event]
-pub struct RequestFulfilled {
-    pub request_id: String,
-    pub response_length: u64,
-}
+    fn generation_params(temperature: u16, max_tokens: u32, top_p: u16) -> GenerationParams {
+        GenerationParams {
+            temperature,
+            max_tokens,
+            top_p,
+            seed: Some(7),
+        }
+    }
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Too many callback accounts (max 32)")]
-    TooManyAccounts,
-    #[msg("Account mismatch in remaining accounts")]
-    AccountMismatch,
-    #[msg("Voting is closed or request already fulfilled")]
-    VotingClosed,
-    #[msg("Callback program does not match")]
-    CallbackProgramMismatch,
-    #[msg("Account count mismatch")]
-    AccountCountMismatch,
-    #[msg("Provider exceeds 64 characters")]
-    ProviderTooLong,
-    #[msg("Model ID exceeds 64 characters")]
-    ModelIdTooLong,
-    #[msg("Too many messages (max 50)")]
-    TooManyMessages,
-    #[msg("Minimum votes must be greater than 0")]
-    InvalidMinVotes,
-    #[msg("Approval threshold must be between 1 and 100")]
-    InvalidApprovalThreshold,
-    #[msg("Oracle has already voted")]
-    OracleAlreadyVoted,
-    #[msg("Too many votes submitted (max 32 oracles)")]
-    TooManyVotes,
-    #[msg("Voting has not been completed yet")]
-    VotingNotCompleted,
-    #[msg("No winning hash available")]
-    NoWinningHash,
-    #[msg("Response hash does not match winning hash")]
-    ResponseHashMismatch,
-}
\ No newline at end of file
+    #[test]
+    fn generation_params_bounds_are_inclusive() {
+        assert!(is_valid_generation_params(&generation_params(0, 1, 0)));
+        assert!(is_valid_generation_params(&generation_params(2_000, 4_096, 1_000)));
+        assert!(!is_valid_generation_params(&generation_params(2_001, 4_096, 1_000)));
+        assert!(!is_valid_generation_params(&generation_params(700, 4_096, 1_001)));
+        assert!(!is_valid_generation_params(&generation_params(700, 0, 900)));
+    }
+
+    fn report(statuses: &mut Vec<ProviderStatus>, oracle: u8, now: i64) -> bool {
+        let oracle = Pubkey::new_from_array([oracle; 32]);
+        record_degraded_report(statuses, "openai", oracle, now).unwrap()
+    }
+
+    #[test]
+    fn quorum_of_reports_flags_provider_until_ttl() {
+        let mut statuses = Vec::new();
+        
+        assert!(!report(&mut statuses, 1, 0));
+        assert!(!report(&mut statuses, 1, 1));
+        assert!(!report(&mut statuses, 2, 2));
+        assert!(!provider_degraded(&statuses, "openai", 2));
+        
+        assert!(report(&mut statuses, 3, 10));
+        assert!(provider_degraded(&statuses, "openai", 10));
+        assert!(!provider_degraded(&statuses, "anthropic", 10));
+        assert!(provider_degraded(&statuses, "openai", 10 + PROVIDER_STATUS_TTL_SECONDS - 1));
+        assert!(!provider_degraded(&statuses, "openai", 10 + PROVIDER_STATUS_TTL_SECONDS));
+    }
+
+    #[test]
+    fn stale_reports_do_not_count_toward_quorum() {
+        let mut statuses = Vec::new();
+        
+        report(&mut statuses, 1, 0);
+        report(&mut statuses, 2, 0);
+        
+        assert!(!report(&mut statuses, 3, PROVIDER_STATUS_TTL_SECONDS));
+        assert!(!provider_degraded(&statuses, "openai", PROVIDER_STATUS_TTL_SECONDS));
+    }
+
+    #[test]
+    fn voting_window_closes_at_deadline() {
+        assert!(!voting_window_closed(None, i64::MAX));
+        assert!(!voting_window_closed(Some(100), 99));
+        assert!(voting_window_closed(Some(100), 100));
+        assert!(voting_window_closed(Some(100), 101));
+    }
+
+    #[test]
+    fn locked_winning_hash_rejects_every_write() {
+        let mut request = blank_request();
+        
+        set_winning_hash(&mut request, None).unwrap();
+        assert!(!request.winning_hash_locked);
+        
+        set_winning_hash(&mut request, Some([1; 32])).unwrap();
+        assert!(request.winning_hash_locked);
+        
+        for write in [Some([1; 32]), Some([2; 32]), None] {
+            let error = set_winning_hash(&mut request, write).unwrap_err();
+            assert_eq!(error, ErrorCode::WinningHashLocked.into());
+        }
+        assert_eq!(request.winning_hash, Some([1; 32]));
+    }
+
+    #[test]
+    fn summary_tracks_leader_through_fulfillment() {
+        let mut request = blank_request();
+        assert_eq!(request_summary(&request).votes_for_leader, 0);
+        
+        request.votes = vec![vote(1, 4, 0), vote(2, 9, 1), vote(3, 9, 2)];
+        request.total_votes_cast = 3;
+        let pending = request_summary(&request);
+        assert!(pending.status == RequestStatus::Pending);
+        assert_eq!(pending.total_votes_cast, 3);
+        assert_eq!(pending.winning_hash, None);
+        assert_eq!(pending.votes_for_leader, 2);
+        
+        set_winning_hash(&mut request, Some([9; 32])).unwrap();
+        request.status = RequestStatus::VotingCompleted;
+        let completed = request_summary(&request);
+        assert!(completed.status == RequestStatus::VotingCompleted);
+        assert_eq!(completed.winning_hash, Some([9; 32]));
+        assert_eq!(completed.votes_for_leader, 2);
+        
+        request.status = RequestStatus::Fulfilled;
+        let fulfilled = request_summary(&request);
+        assert!(fulfilled.status == RequestStatus::Fulfilled);
+        assert_eq!(fulfilled.winning_hash, Some([9; 32]));
+        assert_eq!(fulfilled.votes_for_leader, 2);
+    }
+
+    #[test]
+    fn json_response_needs_json_content_type_when_detected() {
+        let mut request = blank_request();
+        let response = br#"{"answer": 42}"#;
+        
+        assert!(validate_content_type(&request, response, CONTENT_TYPE_TEXT).is_ok());
+        assert!(validate_content_type(&request, response, CONTENT_TYPE_BINARY + 1).is_err());
+        
+        request.detect_format = true;
+        assert!(validate_content_type(&request, response, CONTENT_TYPE_JSON).is_ok());
+        assert!(validate_content_type(&request, response, CONTENT_TYPE_TEXT).is_err());
+        
+        let data = build_callback_data(&request, response, &None, "", CONTENT_TYPE_JSON).unwrap();
+        assert_eq!(data.last(), Some(&CONTENT_TYPE_JSON));
+    }
+
+    #[test]
+    fn raw_bytes_need_binary_content_type_when_detected() {
+        let mut request = blank_request();
+        request.detect_format = true;
+        let response = [0xff, 0x00, 0xfe, 0x01];
+        
+        assert!(validate_content_type(&request, &response, CONTENT_TYPE_BINARY).is_ok());
+        assert!(validate_content_type(&request, &response, CONTENT_TYPE_TEXT).is_err());
+        
+        let data = build_callback_data(&request, &response, &None, "", CONTENT_TYPE_BINARY).unwrap();
+        assert_eq!(data.last(), Some(&CONTENT_TYPE_BINARY));
+    }
+
+    #[test]
+    fn max_oracle_votes_tally_without_overflow() {
+        let mut request = blank_request();
+        let mut counter = event_counter();
+        request.min_votes = u8::MAX;
+        
+        for oracle in 0..MAX_ORACLES {
+            let response_hash = [(oracle % 2) as u8; 32];
+            let oracle_key = Pubkey::new_from_array([oracle as u8; 32]);
+            record_vote(&mut request, &mut counter, oracle_key, response_hash, 1, 0, oracle as i64)
+                .unwrap();
+        }
+        
+        assert_eq!(request.total_votes_cast as usize, MAX_ORACLES);
+        assert!(request.status == RequestStatus::Pending);
+        let tallies = hash_tallies(&request.votes);
+        assert_eq!(tallies.len(), 2);
+        assert!(tallies.iter().all(|tally| tally.count as usize == MAX_ORACLES / 2));
+        
+        request.total_votes_cast = u16::MAX;
+        let error = cast(&mut request, &mut counter, 0, 0).unwrap_err();
+        assert_eq!(error, ErrorCode::VoteCountOverflow.into());
+        assert_eq!(request.votes.len(), MAX_ORACLES);
+    }
+
+    #[test]
+    fn unanimous_votes_wait_for_quorum() {
+        let mut request = blank_request();
+        let mut counter = event_counter();
+        request.min_votes = 2;
+        request.approval_threshold = 66;
+        request.min_participation = 4;
+        
+        for oracle in 0..3 {
+            cast(&mut request, &mut counter, oracle, 7).unwrap();
+            assert!(request.status == RequestStatus::Pending);
+            assert_eq!(request.winning_hash, None);
+        }
+        
+        cast(&mut request, &mut counter, 3, 7).unwrap();
+        assert!(request.status == RequestStatus::VotingCompleted);
+        assert_eq!(request.winning_hash, Some([7; 32]));
+    }
+
+    #[test]
+    fn quorum_counts_dissenting_votes() {
+        let mut request = blank_request();
+        let mut counter = event_counter();
+        request.min_votes = 2;
+        request.approval_threshold = 60;
+        request.min_participation = 3;
+        
+        cast(&mut request, &mut counter, 1, 7).unwrap();
+        cast(&mut request, &mut counter, 2, 7).unwrap();
+        assert!(request.status == RequestStatus::Pending);
+        
+        cast(&mut request, &mut counter, 3, 8).unwrap();
+        assert!(request.status == RequestStatus::VotingCompleted);
+        assert_eq!(request.winning_hash, Some([7; 32]));
+    }
+
+    #[test]
+    fn event_seq_increases_across_lifecycle() {
+        let mut counter = event_counter();
+        let mut seqs = Vec::new();
+        
+        for _ in 0..2 {
+            let mut request = blank_request();
+            request.min_votes = 2;
+            request.approval_threshold = 66;
+            request.min_participation = 2;
+            
+            seqs.push(next_event_seq(&mut counter).unwrap());
+            
+            cast(&mut request, &mut counter, 1, 7).unwrap();
+            assert_eq!(counter.value, *seqs.last().unwrap());
+            cast(&mut request, &mut counter, 2, 7).unwrap();
+            assert!(request.status == RequestStatus::VotingCompleted);
+            seqs.push(counter.value);
+            
+            seqs.push(next_event_seq(&mut counter).unwrap());
+        }
+        
+        assert_eq!(seqs, (1..=6).collect::<Vec<u64>>());
+        
+        counter.value = u64::MAX;
+        let error = next_event_seq(&mut counter).unwrap_err();
+        assert_eq!(error, ErrorCode::EventSeqOverflow.into());
+    }
+
+    #[test]
+    fn fulfilled_request_closes_once_rewards_are_claimed() {
+        let mut request = blank_request();
+        assert_eq!(ensure_closable(&request, 0).unwrap_err(), ErrorCode::RequestNotClosable.into());
+        
+        request.status = RequestStatus::Fulfilled;
+        request.fulfilled_at = Some(100);
+        request.reward_lamports = 1_000;
+        request.winning_hash = Some([7; 32]);
+        request.votes = vec![vote(1, 7, 0), vote(2, 7, 1), vote(3, 8, 2)];
+        assert_eq!(ensure_closable(&request, 100).unwrap_err(), ErrorCode::RewardsUnclaimed.into());
+        
+        request.votes[0].reward_claimed = true;
+        assert_eq!(ensure_closable(&request, 100).unwrap_err(), ErrorCode::RewardsUnclaimed.into());
+        
+        let deadline = 100 + REWARD_CLAIM_WINDOW_SECONDS;
+        assert!(ensure_closable(&request, deadline).is_ok());
+        
+        request.votes[1].reward_claimed = true;
+        assert!(ensure_closable(&request, 100).is_ok());
+        
+        for status in [RequestStatus::Expired, RequestStatus::Cancelled] {
+            request.status = status;
+            assert!(ensure_closable(&request, 100).is_ok());
+        }
+        
+        request.status = RequestStatus::VotingCompleted;
+        assert_eq!(ensure_closable(&request, deadline).unwrap_err(), ErrorCode::RequestNotClosable.into());
+    }
+
+    #[test]
+    fn closing_a_fulfilled_request_returns_rent_and_unclaimed_rewards() {
+        let payer = Pubkey::new_unique();
+        let rent = 5_000;
+        let escrow = 1_000_000;
+        let mut request = blank_request();
+        request.payer = payer;
+        request.status = RequestStatus::Fulfilled;
+        request.fulfilled_at = Some(100);
+        request.reward_lamports = escrow;
+        request.winning_hash = Some([7; 32]);
+        request.votes = vec![vote(1, 7, 0), vote(2, 7, 1)];
+        
+        let oracle = request.votes[0].oracle;
+        let mut input = program_input(&[
+            (Pubkey::new_unique(), crate::ID, rent + escrow, request_data(&request)),
+            (payer, system_program::ID, 0, Vec::new()),
+            (oracle, system_program::ID, 0, Vec::new()),
+        ]);
+        let (_, accounts, _) = unsafe {
+            anchor_lang::solana_program::entrypoint::deserialize(input.as_mut_ptr() as *mut u8)
+        };
+        let (request_info, payer_info, oracle_info) = (&accounts[0], &accounts[1], &accounts[2]);
+        
+        let mut account = Account::<LLMRequest>::try_from(request_info).unwrap();
+        let share = reward_share(&account, &oracle).unwrap();
+        account.votes[0].reward_claimed = true;
+        **request_info.try_borrow_mut_lamports().unwrap() -= share;
+        **oracle_info.try_borrow_mut_lamports().unwrap() += share;
+        
+        let err = ensure_closable(&account, 100).unwrap_err();
+        assert_eq!(err, ErrorCode::RewardsUnclaimed.into());
+        
+        ensure_closable(&account, 100 + REWARD_CLAIM_WINDOW_SECONDS).unwrap();
+        account.close(payer_info.clone()).unwrap();
+        
+        assert_eq!(oracle_info.lamports(), share);
+        assert_eq!(payer_info.lamports(), rent + escrow - share);
+        assert_eq!(request_info.lamports(), 0);
+        assert!(Account::<LLMRequest>::try_from(request_info).is_err());
+    }
+
+    #[test]
+    fn expired_request_refunds_the_payer_once() {
+        let payer = Pubkey::new_unique();
+        let rent = 5_000;
+        let escrow = 1_000_000;
+        let mut request = blank_request();
+        request.payer = payer;
+        request.reward_lamports = escrow;
+        request.expires_at = Some(100);
+        
+        let mut input = program_input(&[
+            (Pubkey::new_unique(), crate::ID, rent + escrow, request_data(&request)),
+            (payer, system_program::ID, 0, Vec::new()),
+        ]);
+        let (_, accounts, _) = unsafe {
+            anchor_lang::solana_program::entrypoint::deserialize(input.as_mut_ptr() as *mut u8)
+        };
+        let (request_info, payer_info) = (&accounts[0], &accounts[1]);
+        
+        let mut account = Account::<LLMRequest>::try_from(request_info).unwrap();
+        let err = expire_for_refund(&mut account, &clock_at(0, 100)).unwrap_err();
+        assert_eq!(err, ErrorCode::RequestNotExpired.into());
+        
+        expire_for_refund(&mut account, &clock_at(0, 101)).unwrap();
+        assert!(account.status == RequestStatus::Expired);
+        account.close(payer_info.clone()).unwrap();
+        
+        assert_eq!(payer_info.lamports(), rent + escrow);
+        assert_eq!(request_info.lamports(), 0);
+        assert!(Account::<LLMRequest>::try_from(request_info).is_err());
+    }
+
+    #[test]
+    fn disputed_request_refunds_the_payer_after_the_challenge_window() {
+        let payer = Pubkey::new_unique();
+        let mut request = blank_request();
+        request.payer = payer;
+        request.status = RequestStatus::Disputed;
+        request.challenge_period_seconds = 60;
+        request.voting_completed_at = Some(1_000);
+        
+        let mut input = program_input(&[
+            (Pubkey::new_unique(), crate::ID, 500_000, request_data(&request)),
+            (payer, system_program::ID, 0, Vec::new()),
+        ]);
+        let (_, accounts, _) = unsafe {
+            anchor_lang::solana_program::entrypoint::deserialize(input.as_mut_ptr() as *mut u8)
+        };
+        let (request_info, payer_info) = (&accounts[0], &accounts[1]);
+        
+        let mut account = Account::<LLMRequest>::try_from(request_info).unwrap();
+        let err = expire_for_refund(&mut account, &clock_at(0, 1_059)).unwrap_err();
+        assert_eq!(err, ErrorCode::RequestNotExpired.into());
+        
+        expire_for_refund(&mut account, &clock_at(0, 1_060)).unwrap();
+        assert!(account.status == RequestStatus::Expired);
+        account.close(payer_info.clone()).unwrap();
+        
+        assert_eq!(payer_info.lamports(), 500_000);
+        assert_eq!(request_info.lamports(), 0);
+    }
+
+    #[test]
+    fn disputed_request_with_deadline_waits_for_it() {
+        let mut request = blank_request();
+        request.status = RequestStatus::Disputed;
+        request.challenge_period_seconds = 60;
+        request.voting_completed_at = Some(1_000);
+        request.expires_at = Some(2_000);
+        
+        let err = ensure_expiry_reached(&request, &clock_at(0, 1_500)).unwrap_err();
+        assert_eq!(err, ErrorCode::RequestNotExpired.into());
+        assert!(ensure_expiry_reached(&request, &clock_at(0, 2_001)).is_ok());
+    }
+
+    #[test]
+    fn multi_provider_hash_ignores_model_identity() {
+        let meta = |model_id: &str| ResponseMeta {
+            model_id: model_id.to_string(),
+            finish_reason: "stop".to_string(),
+        };
+        let mut request = blank_request();
+        
+        assert_ne!(
+            response_hash(&request, b"42", &meta("gpt-4o")),
+            response_hash(&request, b"42", &meta("claude")),
+        );
+        
+        request.providers = vec![ProviderTarget {
+            provider: "anthropic".to_string(),
+            model_id: "claude".to_string(),
+        }];
+        assert_eq!(
+            response_hash(&request, b"42", &meta("gpt-4o")),
+            response_hash(&request, b"42", &meta("claude")),
+        );
+        assert_ne!(
+            response_hash(&request, b"42", &meta("gpt-4o")),
+            response_hash(&request, b"43", &meta("gpt-4o")),
+        );
+    }
+
+    #[test]
+    fn allowlist_still_requires_a_registered_oracle() {
+        let payer = Pubkey::new_unique();
+        let staked = Pubkey::new_unique();
+        let mut request = blank_request();
+        request.payer = payer;
+        request.allowed_oracles = Some(vec![payer, staked]);
+        
+        let registry = OracleRegistry {
+            authority: Pubkey::new_unique(),
+            min_stake: 1_000,
+            deregistration_cooldown_seconds: 0,
+            oracles: vec![RegisteredOracle {
+                oracle: staked,
+                stake: 1_000,
+                deregistering_at: None,
+            }],
+            bump: 0,
+        };
+        let mut registry_data = Vec::new();
+        registry.try_serialize(&mut registry_data).unwrap();
+        let mut counter_data = Vec::new();
+        GlobalCounter { value: 0, bump: 0 }.try_serialize(&mut counter_data).unwrap();
+        
+        let mut input = program_input(&[
+            (Pubkey::new_unique(), crate::ID, 1, request_data(&request)),
+            (Pubkey::new_unique(), system_program::ID, 0, Vec::new()),
+            (Pubkey::new_unique(), crate::ID, 1, registry_data),
+            (Pubkey::new_unique(), crate::ID, 1, counter_data),
+        ]);
+        let (_, infos, _) = unsafe {
+            anchor_lang::solana_program::entrypoint::deserialize(input.as_mut_ptr() as *mut u8)
+        };
+        let mut request = Account::<LLMRequest>::try_from(&infos[0]).unwrap();
+        let registry = Account::<OracleRegistry>::try_from(&infos[2]).unwrap();
+        let mut event_counter = Account::<GlobalCounter>::try_from(&infos[3]).unwrap();
+        let (mut consumer_cooldown, mut oracle_account) = (None, None);
+        
+        let mut accounts = VoteAccounts {
+            request: &mut request,
+            oracle: payer,
+            consumer_cooldown: &mut consumer_cooldown,
+            config: &infos[1],
+            oracle_account: &mut oracle_account,
+            registry: &registry,
+            event_counter: &mut event_counter,
+        };
+        assert!(matches!(authorize_vote(&accounts, 0), Err(ErrorCode::OracleNotRegistered)));
+        
+        accounts.oracle = staked;
+        assert!(authorize_vote(&accounts, 0).is_ok());
+        
+        accounts.request.allowed_oracles = Some(vec![payer]);
+        assert!(matches!(authorize_vote(&accounts, 0), Err(ErrorCode::OracleNotAllowed)));
+    }
+
+    #[test]
+    fn callback_ix_name_must_be_snake_case() {
+        assert!(is_valid_callback_ix_name("llm_callback"));
+        assert!(is_valid_callback_ix_name("on_summary_v2"));
+        assert!(is_valid_callback_ix_name(&"a".repeat(MAX_CALLBACK_IX_NAME_LEN)));
+        assert!(!is_valid_callback_ix_name(""));
+        assert!(!is_valid_callback_ix_name(&"a".repeat(MAX_CALLBACK_IX_NAME_LEN + 1)));
+        assert!(!is_valid_callback_ix_name("2nd_callback"));
+        assert!(!is_valid_callback_ix_name("LlmCallback"));
+        assert!(!is_valid_callback_ix_name("global:llm_callback"));
+    }
+
+    #[test]
+    fn callback_data_targets_custom_callback_name() {
+        let mut request = blank_request();
+        
+        request.callback_ix_name = "llm_callback".to_string();
+        let data = build_callback_data(&request, b"yes", &None, "", CONTENT_TYPE_TEXT).unwrap();
+        assert_eq!(data[..8], hash(b"global:llm_callback").to_bytes()[..8]);
+        
+        request.callback_ix_name = "on_summary".to_string();
+        let data = build_callback_data(&request, b"yes", &None, "", CONTENT_TYPE_TEXT).unwrap();
+        assert_eq!(data[..8], hash(b"global:on_summary").to_bytes()[..8]);
+    }
+
+    #[test]
+    fn reserved_programs_cannot_be_callback_targets() {
+        use anchor_lang::solana_program::sysvar;
+        
+        for key in [crate::ID, system_program::ID, sysvar::clock::ID] {
+            let err = validate_callback_program(&key).unwrap_err();
+            assert_eq!(err, ErrorCode::InvalidCallbackProgram.into());
+        }
+        assert!(validate_callback_program(&Pubkey::new_unique()).is_ok());
+    }
+
+    #[test]
+    fn callback_redirect_rejects_reserved_programs() {
+        let mut request = blank_request();
+        
+        for key in [crate::ID, system_program::ID, ED25519_PROGRAM_ID] {
+            let err = redirect_callback(&mut request, key).unwrap_err();
+            assert_eq!(err, ErrorCode::InvalidCallbackProgram.into());
+        }
+        assert_eq!(request.callback_program_override, None);
+        
+        let target = Pubkey::new_unique();
+        redirect_callback(&mut request, target).unwrap();
+        assert_eq!(request.callback_program_override, Some(target));
+    }
+
+    #[test]
+    fn cache_key_covers_every_output_parameter() {
+        let mut request = blank_request();
+        request.conversation_hash = [7; 32];
+        request.model_id = "gpt-4o".to_string();
+        request.generation_params = Some(GenerationParams {
+            temperature: 700,
+            max_tokens: 256,
+            top_p: 1000,
+            seed: Some(42),
+        });
+        request.response_constraint = Some(ResponseConstraint::NumericRange(1, 10));
+        
+        let key = result_cache_key(&request).unwrap();
+        let expected = coolrouter_cpi::result_cache_key(
+            &[7; 32],
+            "",
+            "gpt-4o",
+            &[],
+            &Some(coolrouter_cpi::GenerationParams {
+                temperature: 700,
+                max_tokens: 256,
+                top_p: 1000,
+                seed: Some(42),
+            }),
+            &Some(coolrouter_cpi::ResponseConstraint::NumericRange(1, 10)),
+            &coolrouter_cpi::NormalizeMode::Raw,
+            &coolrouter_cpi::ResponseKind::Text,
+        )
+        .unwrap();
+        assert_eq!(key, expected);
+        
+        let mut other = request.clone();
+        other.generation_params.as_mut().unwrap().temperature = 0;
+        assert_ne!(result_cache_key(&other).unwrap(), key);
+        
+        let mut other = request.clone();
+        other.normalize = NormalizeMode::TrimTrailingWhitespace;
+        assert_ne!(result_cache_key(&other).unwrap(), key);
+        
+        let mut other = request.clone();
+        other.response_kind = ResponseKind::ToolCall;
+        assert_ne!(result_cache_key(&other).unwrap(), key);
+    }
+
+    #[test]
+    fn restricted_requests_never_write_the_cache() {
+        let mut request = blank_request();
+        assert!(!writes_result_cache(&request));
+        
+        request.allow_cache = true;
+        assert!(writes_result_cache(&request));
+        
+        let mut allowlisted = request.clone();
+        allowlisted.allowed_oracles = Some(vec![Pubkey::new_unique()]);
+        assert!(!writes_result_cache(&allowlisted));
+        
+        let mut trusted = request.clone();
+        trusted.trusted_oracle = Some(Pubkey::new_unique());
+        assert!(!writes_result_cache(&trusted));
+        
+        let mut encrypted = request.clone();
+        encrypted.encrypt_to = Some([3; 32]);
+        assert!(!writes_result_cache(&encrypted));
+    }
+
+    #[test]
+    fn stored_response_reads_back_after_fulfillment() {
+        let mut request = blank_request();
+        let response = vec![b'x'; MAX_STORED_RESPONSE];
+        
+        store_response(&mut request, &response);
+        assert_eq!(request.stored_response, None);
+        
+        request.store_response_onchain = true;
+        store_response(&mut request, &response);
+        
+        let mut data = Vec::new();
+        request.try_serialize(&mut data).unwrap();
+        assert!(data.len() <= LLM_REQUEST_SPACE + 4 + MAX_STORED_RESPONSE);
+        
+        let stored = LLMRequest::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(stored.stored_response, Some(response));
+    }
+
+    #[test]
+    fn batch_skips_requests_that_store_the_response() {
+        let mut request = blank_request();
+        request.store_response_onchain = true;
+        
+        let err = validate_batch_request(&request, b"yes", CONTENT_TYPE_TEXT).unwrap_err();
+        assert_eq!(err, ErrorCode::StoredResponseNotBatchable.into());
+    }
+}
@@ -1,79 +1,267 @@
 use anchor_lang::prelude::*;
-use coolrouter_cpi::{create_llm_request, Message};
+use solana_program::hash::hash;
+use coolrouter_cpi::{
+    acknowledge_delivery, canonical_messages_bytes, canonical_response_bytes, CoolRouterCPI,
+    Message, ResponseMeta, CONTENT_TYPE_BINARY, CONTENT_TYPE_TEXT, COOLROUTER_PROGRAM_ID,
+    FORMAT_UNDETECTED,
+};
 
 declare_id!("BrRX5CdLjXZDPzaQFY1BnjdsLeqMED1JeKKSjpnaxU1R");
 
 const MAX_REQUEST_ID_LEN: usize = 60;
 const MAX_RESPONSE_LEN: usize = 2000;
+const MAX_MODEL_ID_LEN: usize = 64;
+const MAX_AGGREGATED_REQUESTS: usize = 4;
+const MAX_AGGREGATE_RESPONSE_LEN: usize = 1000;
+const MAX_CONVERSATION_MESSAGES: usize = 16;
+const MAX_ROLE_LEN: usize = 16;
+const MAX_CONVERSATION_MESSAGE_LEN: usize = 256;
+/// Responses a history-mode `consumer_state` keeps. Bounded so the account
+/// stays under the 10 KiB limit for accounts created through CPI.
+const MAX_HISTORY_ENTRIES: usize = 3;
+
+pub const CONSUMER_STATE_SEED: &[u8] = b"consumer_state";
 
 const ACCOUNT_SPACE: usize = 8
     + (4 + MAX_REQUEST_ID_LEN)
     + (4 + MAX_RESPONSE_LEN)
     + 1
-    + 32;
+    + 32
+    + (1 + 32 + 64)
+    + 32
+    + 4
+    + 4
+    + 1
+    + (4 + MAX_MODEL_ID_LEN)
+    + 1
+    + (1 + 32)
+    + 1
+    + 1
+    + 1
+    + 4;
+
+const HISTORY_ENTRY_SPACE: usize = (4 + MAX_REQUEST_ID_LEN) + (4 + MAX_RESPONSE_LEN) + 8;
+
+const HISTORY_ACCOUNT_SPACE: usize = ACCOUNT_SPACE + MAX_HISTORY_ENTRIES * HISTORY_ENTRY_SPACE;
+
+// An account created through a CPI can be at most 10 KiB.
+const _: () = assert!(HISTORY_ACCOUNT_SPACE <= 10_240);
+
+const AGGREGATE_SPACE: usize = 8
+    + (4 + MAX_REQUEST_ID_LEN)
+    + 32
+    + 1
+    + 1
+    + 1
+    + 1
+    + (4 + MAX_AGGREGATED_REQUESTS
+        * ((4 + MAX_REQUEST_ID_LEN) + (4 + MAX_AGGREGATE_RESPONSE_LEN) + 1 + 1));
+
+const CONVERSATION_SPACE: usize = 8
+    + (4 + MAX_REQUEST_ID_LEN)
+    + 32
+    + (4 + MAX_CONVERSATION_MESSAGES
+        * ((4 + MAX_ROLE_LEN) + (4 + MAX_CONVERSATION_MESSAGE_LEN)));
 
 #[program]
 pub mod llm_consumer {
     use super::*;
 
+    #[allow(clippy::too_many_arguments)]
     pub fn request_llm_response(
         ctx: Context<RequestLLMResponse>,
         request_id: String,
         prompt: String,
         min_votes: u8,
         approval_threshold: u8,
+        min_len: u32,
+        max_len: u32,
+        require_utf8: bool,
+        response_kind: ResponseKind,
+        reward_lamports: u64,
+    ) -> Result<()> {
+        send_llm_request(
+            &mut ctx.accounts.consumer_state,
+            &ctx.accounts.authority,
+            &ctx.accounts.system_program,
+            &mut ctx.accounts.common,
+            request_id,
+            prompt,
+            min_votes,
+            approval_threshold,
+            min_len,
+            max_len,
+            require_utf8,
+            response_kind,
+            reward_lamports,
+        )
+    }
+
+    /// Creates the single `consumer_state` at `[CONSUMER_STATE_SEED, authority]`
+    /// that `request_llm_response_history` reuses for every request.
+    pub fn init_history(ctx: Context<InitHistory>) -> Result<()> {
+        let consumer_state = &mut ctx.accounts.consumer_state;
+        consumer_state.authority = ctx.accounts.authority.key();
+        consumer_state.history_mode = true;
+        consumer_state.history = Vec::new();
+        
+        Ok(())
+    }
+
+    /// `request_llm_response` against the authority's history-mode
+    /// `consumer_state` instead of a fresh account per request. Only one
+    /// request is tracked at a time: a new request replaces one still in
+    /// flight, whose callback then fails with `RequestIdMismatch`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn request_llm_response_history(
+        ctx: Context<RequestLLMResponseHistory>,
+        request_id: String,
+        prompt: String,
+        min_votes: u8,
+        approval_threshold: u8,
+        min_len: u32,
+        max_len: u32,
+        require_utf8: bool,
+        response_kind: ResponseKind,
+        reward_lamports: u64,
+    ) -> Result<()> {
+        send_llm_request(
+            &mut ctx.accounts.consumer_state,
+            &ctx.accounts.authority,
+            &ctx.accounts.system_program,
+            &mut ctx.accounts.common,
+            request_id,
+            prompt,
+            min_votes,
+            approval_threshold,
+            min_len,
+            max_len,
+            require_utf8,
+            response_kind,
+            reward_lamports,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn llm_callback<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LLMCallback<'info>>,
+        request_id: String,
+        response: Vec<u8>,
+        attestation: Option<OracleAttestation>,
+        conversation_hash: [u8; 32],
+        served_model_id: String,
+        response_kind: ResponseKind,
+        detected_format: u8,
+        distribution: Option<Vec<HashTally>>,
+        content_type: u8,
     ) -> Result<()> {
         let consumer_state = &mut ctx.accounts.consumer_state;
         
         require!(
-            request_id.len() <= MAX_REQUEST_ID_LEN,
-            ErrorCode::RequestIdTooLong
+            consumer_state.request_id == request_id,
+            ErrorCode::RequestIdMismatch
         );
         
-        require!(min_votes > 0, ErrorCode::InvalidMinVotes);
         require!(
-            approval_threshold > 0 && approval_threshold <= 100,
-            ErrorCode::InvalidApprovalThreshold
+            consumer_state.response_kind == response_kind,
+            ErrorCode::ResponseKindMismatch
         );
         
-        consumer_state.request_id = request_id.clone();
-        consumer_state.response = Vec::new();
-        consumer_state.has_response = false;
-        consumer_state.authority = ctx.accounts.authority.key();
+        require!(
+            consumer_state.conversation_hash == conversation_hash,
+            ErrorCode::ConversationHashMismatch
+        );
         
-        let messages = vec![Message {
-            role: "user".to_string(),
-            content: prompt,
-        }];
+        require!(
+            response.len() <= MAX_RESPONSE_LEN,
+            ErrorCode::ResponseTooLarge
+        );
         
-        let callback_accounts = vec![
-            ctx.accounts.consumer_state.to_account_info(),
-        ];
+        require!(
+            served_model_id.len() <= MAX_MODEL_ID_LEN,
+            ErrorCode::ModelIdTooLong
+        );
         
-        create_llm_request(
-            ctx.accounts.request_pda.to_account_info(),
-            ctx.accounts.authority.to_account_info(),
-            ctx.accounts.consumer_program.to_account_info(),
-            ctx.accounts.system_program.to_account_info(),
-            ctx.accounts.coolrouter_program.key(),
-            callback_accounts,
-            request_id.clone(),
-            "openai".to_string(),
-            "gpt-4".to_string(),
-            messages,
-            min_votes,
-            approval_threshold,
-        )?;
+        require!(
+            content_type <= CONTENT_TYPE_BINARY,
+            ErrorCode::InvalidContentType
+        );
+        
+        if let Err(error) = validate_response(consumer_state, &response) {
+            emit!(ResponseRejected {
+                request_id,
+                error_code: error.into(),
+            });
+            
+            msg!("LLM response rejected by validation");
+            
+            return Ok(());
+        }
+        
+        consumer_state.response = response.clone();
+        consumer_state.has_response = true;
+        consumer_state.attestation = attestation;
+        consumer_state.served_model_id = served_model_id;
+        consumer_state.detected_format = detected_format;
+        consumer_state.content_type = content_type;
+        
+        if consumer_state.history_mode {
+            push_history_entry(
+                &mut consumer_state.history,
+                HistoryEntry {
+                    request_id: request_id.clone(),
+                    response: response.clone(),
+                    received_at: Clock::get()?.unix_timestamp,
+                },
+            );
+        }
+        
+        if let Some(tallies) = &distribution {
+            msg!("Vote distribution: {} distinct responses", tallies.len());
+        }
+        
+        if let Some(aggregate_info) = ctx.remaining_accounts.first() {
+            let mut aggregate = Account::<AggregateState>::try_from(aggregate_info)?;
+            record_aggregate_response(&mut aggregate, &request_id, &response)?;
+            aggregate.exit(&crate::ID)?;
+        }
+        
+        let response_preview = match response_kind {
+            ResponseKind::Text if content_type == CONTENT_TYPE_BINARY => {
+                format!("[Binary data: {} bytes]", response.len())
+            }
+            ResponseKind::Text => String::from_utf8(response.clone())
+                .unwrap_or_else(|_| format!("[Binary data: {} bytes]", response.len())),
+            ResponseKind::ToolCall => {
+                let tool_call = ToolCall::try_from_slice(&response)
+                    .map_err(|_| ErrorCode::MalformedToolCall)?;
+                format!(
+                    "[Tool call: {} ({} argument bytes)]",
+                    tool_call.tool_name,
+                    tool_call.arguments.len()
+                )
+            }
+        }
+        .chars()
+        .take(100)
+        .collect();
+        
+        emit!(ResponseReceived {
+            request_id,
+            response_preview,
+            content_type,
+        });
         
-        msg!("LLM request created with ID: {}", request_id);
+        msg!("LLM response received and stored");
         
         Ok(())
     }
 
-    pub fn llm_callback(
+    pub fn llm_notify(
         ctx: Context<LLMCallback>,
         request_id: String,
-        response: Vec<u8>,
+        response_hash: [u8; 32],
+        response_length: u64,
     ) -> Result<()> {
         let consumer_state = &mut ctx.accounts.consumer_state;
         
@@ -82,30 +270,303 @@ pub mod llm_consumer {
             ErrorCode::RequestIdMismatch
         );
         
+        consumer_state.pending_response_hash = Some(response_hash);
+        
+        emit!(ResponseAvailable {
+            request_id,
+            response_hash,
+            response_length,
+        });
+        
+        msg!("LLM response available for pull");
+        
+        Ok(())
+    }
+
+    pub fn pull_response(ctx: Context<PullResponse>) -> Result<()> {
+        let consumer_state = &mut ctx.accounts.consumer_state;
+        
+        require_keys_eq!(
+            consumer_state.authority,
+            ctx.accounts.authority.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require_keys_eq!(
+            *ctx.accounts.response_buffer.owner,
+            ctx.accounts.coolrouter_program.key(),
+            ErrorCode::InvalidResponseBuffer
+        );
+        
+        let pending_hash = consumer_state
+            .pending_response_hash
+            .ok_or(ErrorCode::NoResponse)?;
+        
+        let data = ctx.accounts.response_buffer.try_borrow_data()?;
+        let mut buffer_data = data.get(8..).ok_or(ErrorCode::InvalidResponseBuffer)?;
+        let buffer = RouterResponseBuffer::deserialize(&mut buffer_data)
+            .map_err(|_| ErrorCode::InvalidResponseBuffer)?;
+        
+        require!(
+            hash(&canonical_response_bytes(
+                &consumer_state.request_id,
+                &buffer.response,
+                &buffer.metadata,
+            ))
+            .to_bytes()
+                == pending_hash,
+            ErrorCode::ResponseHashMismatch
+        );
+        
         require!(
-            response.len() <= MAX_RESPONSE_LEN,
+            buffer.response.len() <= MAX_RESPONSE_LEN,
             ErrorCode::ResponseTooLarge
         );
         
-        consumer_state.response = response.clone();
+        validate_response(consumer_state, &buffer.response)?;
+        
+        consumer_state.response = buffer.response;
         consumer_state.has_response = true;
+        consumer_state.pending_response_hash = None;
+        
+        msg!("LLM response pulled and stored");
+        
+        Ok(())
+    }
+
+    pub fn confirm_delivery(ctx: Context<ConfirmDelivery>) -> Result<()> {
+        let consumer_state = &ctx.accounts.consumer_state;
+        
+        require_keys_eq!(
+            consumer_state.authority,
+            ctx.accounts.authority.key(),
+            ErrorCode::Unauthorized
+        );
         
-        let response_preview = String::from_utf8(response.clone())
-            .unwrap_or_else(|_| format!("[Binary data: {} bytes]", response.len()))
-            .chars()
-            .take(100)
+        require!(consumer_state.has_response, ErrorCode::NoResponse);
+        
+        let request_id = consumer_state.request_id.as_bytes();
+        let bump = [ctx.bumps.delivery_ack_authority];
+        let signer_seeds: &[&[&[u8]]] = &[&[b"delivery_ack", request_id, &bump]];
+        
+        acknowledge_delivery(
+            ctx.accounts.request_pda.to_account_info(),
+            ctx.accounts.delivery_ack_authority.to_account_info(),
+            ctx.accounts.coolrouter_program.key(),
+            signer_seeds,
+        )?;
+        
+        msg!("Delivery confirmed for request: {}", consumer_state.request_id);
+        
+        Ok(())
+    }
+
+    pub fn create_aggregate(
+        ctx: Context<CreateAggregate>,
+        aggregate_id: String,
+        request_ids: Vec<String>,
+        required_count: u8,
+    ) -> Result<()> {
+        let aggregate = &mut ctx.accounts.aggregate;
+        
+        require!(
+            aggregate_id.len() <= MAX_REQUEST_ID_LEN,
+            ErrorCode::RequestIdTooLong
+        );
+        require!(
+            !request_ids.is_empty() && request_ids.len() <= MAX_AGGREGATED_REQUESTS,
+            ErrorCode::InvalidAggregate
+        );
+        require!(
+            request_ids.iter().all(|request_id| request_id.len() <= MAX_REQUEST_ID_LEN),
+            ErrorCode::RequestIdTooLong
+        );
+        require!(
+            required_count > 0 && required_count as usize <= request_ids.len(),
+            ErrorCode::InvalidAggregate
+        );
+        
+        aggregate.aggregate_id = aggregate_id;
+        aggregate.authority = ctx.accounts.authority.key();
+        aggregate.required_count = required_count;
+        aggregate.received_count = 0;
+        aggregate.failed_count = 0;
+        aggregate.complete = false;
+        aggregate.entries = request_ids
+            .into_iter()
+            .map(|request_id| AggregateEntry {
+                request_id,
+                response: Vec::new(),
+                has_response: false,
+                failed: false,
+            })
             .collect();
         
-        emit!(ResponseReceived {
-            request_id,
-            response_preview,
-        });
+        Ok(())
+    }
+
+    pub fn get_all_responses(ctx: Context<GetAggregate>) -> Result<Vec<AggregateEntry>> {
+        let aggregate = &ctx.accounts.aggregate;
         
-        msg!("LLM response received and stored");
+        require_keys_eq!(
+            aggregate.authority,
+            ctx.accounts.authority.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        require!(aggregate.complete, ErrorCode::AggregateIncomplete);
+        
+        Ok(aggregate.entries.clone())
+    }
+
+    /// Records that a sub-request will never deliver (expired, cancelled or
+    /// callback failure observed by the authority). Failures count towards
+    /// `required_count` so the aggregate still completes.
+    pub fn mark_aggregate_failure(
+        ctx: Context<MarkAggregateFailure>,
+        request_id: String,
+    ) -> Result<()> {
+        let aggregate = &mut ctx.accounts.aggregate;
+        
+        require_keys_eq!(
+            aggregate.authority,
+            ctx.accounts.authority.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        let entry = aggregate
+            .entries
+            .iter_mut()
+            .find(|entry| entry.request_id == request_id)
+            .ok_or(ErrorCode::RequestNotInAggregate)?;
+        
+        if entry.has_response || entry.failed {
+            return Ok(());
+        }
+        
+        entry.failed = true;
+        aggregate.failed_count += 1;
+        
+        check_aggregate_complete(aggregate);
         
         Ok(())
     }
 
+    pub fn create_conversation(
+        ctx: Context<CreateConversation>,
+        conversation_id: String,
+    ) -> Result<()> {
+        require!(
+            conversation_id.len() <= MAX_REQUEST_ID_LEN,
+            ErrorCode::RequestIdTooLong
+        );
+        
+        let conversation = &mut ctx.accounts.conversation;
+        conversation.conversation_id = conversation_id;
+        conversation.authority = ctx.accounts.authority.key();
+        conversation.messages = Vec::new();
+        
+        Ok(())
+    }
+
+    /// Appends a message, typically the assistant's reply from a delivered
+    /// response, so the next request carries the full history.
+    pub fn append_message(
+        ctx: Context<UpdateConversation>,
+        role: String,
+        content: String,
+    ) -> Result<()> {
+        let conversation = &mut ctx.accounts.conversation;
+        
+        require_keys_eq!(
+            conversation.authority,
+            ctx.accounts.authority.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        push_conversation_message(conversation, role, content)
+    }
+
+    /// Replaces the oldest `count` messages with a single system message
+    /// holding a caller-supplied summary, freeing room for new turns.
+    pub fn compress_context(
+        ctx: Context<UpdateConversation>,
+        count: u8,
+        summary: String,
+    ) -> Result<()> {
+        let conversation = &mut ctx.accounts.conversation;
+        
+        require_keys_eq!(
+            conversation.authority,
+            ctx.accounts.authority.key(),
+            ErrorCode::Unauthorized
+        );
+        require!(
+            count > 0 && count as usize <= conversation.messages.len(),
+            ErrorCode::InvalidCompression
+        );
+        require!(
+            summary.len() <= MAX_CONVERSATION_MESSAGE_LEN,
+            ErrorCode::MessageTooLong
+        );
+        
+        conversation.messages.drain(..count as usize);
+        conversation.messages.insert(
+            0,
+            ConversationMessage {
+                role: "system".to_string(),
+                content: summary,
+            },
+        );
+        
+        emit!(ContextCompressed {
+            conversation_id: conversation.conversation_id.clone(),
+            replaced: count,
+            remaining: conversation.messages.len() as u8,
+        });
+        
+        Ok(())
+    }
+
+    pub fn compare_responses(
+        ctx: Context<CompareResponses>,
+        request_id_a: String,
+        request_id_b: String,
+    ) -> Result<ResponseComparison> {
+        let state_a = &ctx.accounts.state_a;
+        let state_b = &ctx.accounts.state_b;
+        let authority = ctx.accounts.authority.key();
+        
+        require_keys_eq!(state_a.authority, authority, ErrorCode::Unauthorized);
+        require_keys_eq!(state_b.authority, authority, ErrorCode::Unauthorized);
+        
+        require!(
+            state_a.has_response && state_b.has_response,
+            ErrorCode::NoResponse
+        );
+        
+        let common_prefix_len = state_a
+            .response
+            .iter()
+            .zip(state_b.response.iter())
+            .take_while(|(a, b)| a == b)
+            .count() as u32;
+        
+        msg!(
+            "Compared responses for {} and {}: {} common prefix bytes",
+            request_id_a,
+            request_id_b,
+            common_prefix_len
+        );
+        
+        Ok(ResponseComparison {
+            hashes_match: hash(&state_a.response) == hash(&state_b.response),
+            common_prefix_len,
+            len_a: state_a.response.len() as u32,
+            len_b: state_b.response.len() as u32,
+        })
+    }
+
     pub fn get_response(ctx: Context<GetResponse>) -> Result<Vec<u8>> {
         let consumer_state = &ctx.accounts.consumer_state;
         
@@ -119,6 +580,273 @@ pub mod llm_consumer {
         
         Ok(consumer_state.response.clone())
     }
+
+    pub fn get_response_by_id(
+        ctx: Context<GetResponse>,
+        request_id: String,
+    ) -> Result<Vec<u8>> {
+        let consumer_state = &ctx.accounts.consumer_state;
+        
+        require_keys_eq!(
+            consumer_state.authority,
+            ctx.accounts.authority.key(),
+            ErrorCode::Unauthorized
+        );
+        
+        consumer_state
+            .history
+            .iter()
+            .find(|entry| entry.request_id == request_id)
+            .map(|entry| entry.response.clone())
+            .ok_or(error!(ErrorCode::NoResponse))
+    }
+}
+
+/// Shared body of `request_llm_response` and `request_llm_response_history`:
+/// resets `consumer_state` for `request_id` and sends the request to the router.
+#[allow(clippy::too_many_arguments)]
+fn send_llm_request<'info>(
+    consumer_state: &mut Account<'info, ConsumerState>,
+    authority: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    accounts: &mut LLMRequestCommon<'info>,
+    request_id: String,
+    prompt: String,
+    min_votes: u8,
+    approval_threshold: u8,
+    min_len: u32,
+    max_len: u32,
+    require_utf8: bool,
+    response_kind: ResponseKind,
+    reward_lamports: u64,
+) -> Result<()> {
+    
+    require!(
+        request_id.len() <= MAX_REQUEST_ID_LEN,
+        ErrorCode::RequestIdTooLong
+    );
+    
+    require!(
+        authority.lamports() >= reward_lamports,
+        ErrorCode::InsufficientBalanceForReward
+    );
+    
+    require!(min_votes > 0, ErrorCode::InvalidMinVotes);
+    require!(
+        approval_threshold > 0 && approval_threshold <= 100,
+        ErrorCode::InvalidApprovalThreshold
+    );
+    require!(
+        min_len <= max_len && max_len as usize <= MAX_RESPONSE_LEN,
+        ErrorCode::InvalidResponseBounds
+    );
+    
+    if let Some(aggregate) = &accounts.aggregate {
+        require_keys_eq!(
+            aggregate.authority,
+            authority.key(),
+            ErrorCode::Unauthorized
+        );
+        require!(
+            aggregate.entries.iter().any(|entry| entry.request_id == request_id),
+            ErrorCode::RequestNotInAggregate
+        );
+        require!(
+            max_len as usize <= MAX_AGGREGATE_RESPONSE_LEN,
+            ErrorCode::InvalidResponseBounds
+        );
+    }
+    
+    consumer_state.request_id = request_id.clone();
+    consumer_state.response = Vec::new();
+    consumer_state.has_response = false;
+    consumer_state.authority = authority.key();
+    consumer_state.attestation = None;
+    consumer_state.min_len = min_len;
+    consumer_state.max_len = max_len;
+    consumer_state.require_utf8 = require_utf8;
+    consumer_state.served_model_id = String::new();
+    consumer_state.response_kind = response_kind.clone();
+    consumer_state.pending_response_hash = None;
+    consumer_state.detected_format = FORMAT_UNDETECTED;
+    consumer_state.content_type = CONTENT_TYPE_TEXT;
+    
+    let messages = match accounts.conversation.as_mut() {
+        Some(conversation) => {
+            require_keys_eq!(
+                conversation.authority,
+                authority.key(),
+                ErrorCode::Unauthorized
+            );
+            push_conversation_message(conversation, "user".to_string(), prompt)?;
+            
+            conversation
+                .messages
+                .iter()
+                .map(|message| Message {
+                    role: message.role.clone(),
+                    content: message.content.clone(),
+                })
+                .collect()
+        }
+        None => vec![Message {
+            role: "user".to_string(),
+            content: prompt,
+        }],
+    };
+    
+    consumer_state.conversation_hash = hash(&canonical_messages_bytes(&messages)).to_bytes();
+    
+    let mut callback_accounts = vec![
+        consumer_state.to_account_info(),
+    ];
+    if let Some(aggregate) = &accounts.aggregate {
+        callback_accounts.push(aggregate.to_account_info());
+    }
+    
+    let router_response_kind = match response_kind {
+        ResponseKind::Text => coolrouter_cpi::ResponseKind::Text,
+        ResponseKind::ToolCall => coolrouter_cpi::ResponseKind::ToolCall,
+    };
+    
+    CoolRouterCPI::new(
+        accounts.request_pda.to_account_info(),
+        authority.to_account_info(),
+        accounts.consumer_program.to_account_info(),
+        system_program.to_account_info(),
+        accounts.router_config.to_account_info(),
+        accounts.router_event_counter.to_account_info(),
+        accounts.coolrouter_program.key(),
+    )
+    .add_callback_accounts(callback_accounts)
+    .with_response_kind(router_response_kind)
+    .with_reward(reward_lamports, 0)
+    .with_format_detection(true)
+    .create_request(
+        request_id.clone(),
+        "openai".to_string(),
+        "gpt-4".to_string(),
+        messages,
+        min_votes,
+        approval_threshold,
+    )?;
+    
+    msg!("LLM request created with ID: {}", request_id);
+    
+    Ok(())
+}
+
+/// Address of the `consumer_state` PDA that `request_llm_response` creates for
+/// `authority` and `request_id` under `program_id`.
+pub fn derive_consumer_state_pda(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    request_id: &str,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[CONSUMER_STATE_SEED, authority.as_ref(), request_id.as_bytes()],
+        program_id,
+    )
+}
+
+/// Address of the history-mode `consumer_state` that `init_history` creates
+/// for `authority` under `program_id`.
+pub fn derive_history_state_pda(program_id: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONSUMER_STATE_SEED, authority.as_ref()], program_id)
+}
+
+/// Appends `entry`, evicting the oldest entry once `MAX_HISTORY_ENTRIES` are held.
+fn push_history_entry(history: &mut Vec<HistoryEntry>, entry: HistoryEntry) {
+    if history.len() >= MAX_HISTORY_ENTRIES {
+        history.remove(0);
+    }
+    history.push(entry);
+}
+
+fn record_aggregate_response(
+    aggregate: &mut AggregateState,
+    request_id: &str,
+    response: &[u8],
+) -> Result<()> {
+    let entry = aggregate
+        .entries
+        .iter_mut()
+        .find(|entry| entry.request_id == request_id)
+        .ok_or(ErrorCode::RequestNotInAggregate)?;
+    
+    if entry.has_response || entry.failed {
+        return Ok(());
+    }
+    
+    entry.response = response.to_vec();
+    entry.has_response = true;
+    aggregate.received_count += 1;
+    
+    check_aggregate_complete(aggregate);
+    
+    Ok(())
+}
+
+/// Marks the aggregate complete once responses plus failures reach
+/// `required_count`, emitting `AllResponsesReady` exactly once.
+fn check_aggregate_complete(aggregate: &mut AggregateState) {
+    if aggregate.complete
+        || aggregate.received_count + aggregate.failed_count < aggregate.required_count
+    {
+        return;
+    }
+    
+    aggregate.complete = true;
+    
+    emit!(AllResponsesReady {
+        aggregate_id: aggregate.aggregate_id.clone(),
+        received_count: aggregate.received_count,
+        failed_count: aggregate.failed_count,
+    });
+}
+
+fn push_conversation_message(
+    conversation: &mut ConversationState,
+    role: String,
+    content: String,
+) -> Result<()> {
+    require!(
+        role.len() <= MAX_ROLE_LEN && content.len() <= MAX_CONVERSATION_MESSAGE_LEN,
+        ErrorCode::MessageTooLong
+    );
+    require!(
+        conversation.messages.len() < MAX_CONVERSATION_MESSAGES,
+        ErrorCode::ConversationFull
+    );
+    
+    conversation.messages.push(ConversationMessage { role, content });
+    
+    Ok(())
+}
+
+fn validate_response(
+    consumer_state: &ConsumerState,
+    response: &[u8],
+) -> std::result::Result<(), ErrorCode> {
+    if response.len() < consumer_state.min_len as usize {
+        return Err(ErrorCode::ResponseTooShortConsumer);
+    }
+    
+    if response.len() > consumer_state.max_len as usize {
+        return Err(ErrorCode::ResponseTooLongConsumer);
+    }
+    
+    if consumer_state.require_utf8 && std::str::from_utf8(response).is_err() {
+        return Err(ErrorCode::ResponseNotUtf8);
+    }
+    
+    if consumer_state.response_kind == ResponseKind::ToolCall
+        && ToolCall::try_from_slice(response).is_err()
+    {
+        return Err(ErrorCode::MalformedToolCall);
+    }
+    
+    Ok(())
 }
 
 #[derive(Accounts)]
@@ -128,7 +856,7 @@ pub struct RequestLLMResponse<'info> {
         init,
         payer = authority,
         space = ACCOUNT_SPACE,
-        seeds = [b"consumer_state", authority.key().as_ref(), request_id.as_bytes()],
+        seeds = [CONSUMER_STATE_SEED, authority.key().as_ref(), request_id.as_bytes()],
         bump
     )]
     pub consumer_state: Account<'info, ConsumerState>,
@@ -136,6 +864,51 @@ pub struct RequestLLMResponse<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
     
+    pub common: LLMRequestCommon<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitHistory<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = HISTORY_ACCOUNT_SPACE,
+        seeds = [CONSUMER_STATE_SEED, authority.key().as_ref()],
+        bump
+    )]
+    pub consumer_state: Account<'info, ConsumerState>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RequestLLMResponseHistory<'info> {
+    #[account(
+        mut,
+        seeds = [CONSUMER_STATE_SEED, authority.key().as_ref()],
+        bump,
+        constraint = consumer_state.history_mode @ ErrorCode::NotHistoryState
+    )]
+    pub consumer_state: Account<'info, ConsumerState>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub common: LLMRequestCommon<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts every request needs besides its `consumer_state`, `authority`
+/// and `system_program`, which stay at the top level so Anchor can see the
+/// payer and system program of the `init`.
+#[derive(Accounts)]
+pub struct LLMRequestCommon<'info> {
     /// CHECK: PDA for the request in CoolRouter
     #[account(mut)]
     pub request_pda: AccountInfo<'info>,
@@ -146,7 +919,18 @@ pub struct RequestLLMResponse<'info> {
     /// CHECK: The CoolRouter program
     pub coolrouter_program: AccountInfo<'info>,
     
-    pub system_program: Program<'info, System>,
+    /// CHECK: CoolRouter's global config PDA, validated by CoolRouter
+    pub router_config: AccountInfo<'info>,
+    
+    /// CHECK: CoolRouter's event sequence PDA, validated by CoolRouter
+    #[account(mut)]
+    pub router_event_counter: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub aggregate: Option<Account<'info, AggregateState>>,
+    
+    #[account(mut)]
+    pub conversation: Option<Account<'info, ConversationState>>,
 }
 
 #[derive(Accounts)]
@@ -155,6 +939,114 @@ pub struct LLMCallback<'info> {
     pub consumer_state: Account<'info, ConsumerState>,
 }
 
+#[derive(Accounts)]
+pub struct PullResponse<'info> {
+    #[account(mut)]
+    pub consumer_state: Account<'info, ConsumerState>,
+    pub authority: Signer<'info>,
+    
+    /// CHECK: CoolRouter response buffer, verified by owner and response hash
+    pub response_buffer: AccountInfo<'info>,
+    
+    /// CHECK: The CoolRouter program, pinned to its deployed address
+    #[account(address = COOLROUTER_PROGRAM_ID)]
+    pub coolrouter_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmDelivery<'info> {
+    pub consumer_state: Account<'info, ConsumerState>,
+    pub authority: Signer<'info>,
+    
+    /// CHECK: PDA for the request in CoolRouter
+    #[account(mut)]
+    pub request_pda: AccountInfo<'info>,
+    
+    /// CHECK: Signing PDA recognised by CoolRouter for delivery acknowledgments
+    #[account(
+        seeds = [b"delivery_ack", consumer_state.request_id.as_bytes()],
+        bump
+    )]
+    pub delivery_ack_authority: AccountInfo<'info>,
+    
+    /// CHECK: The CoolRouter program
+    pub coolrouter_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(aggregate_id: String)]
+pub struct CreateAggregate<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = AGGREGATE_SPACE,
+        seeds = [b"aggregate", authority.key().as_ref(), aggregate_id.as_bytes()],
+        bump
+    )]
+    pub aggregate: Account<'info, AggregateState>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(conversation_id: String)]
+pub struct CreateConversation<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = CONVERSATION_SPACE,
+        seeds = [b"conversation", authority.key().as_ref(), conversation_id.as_bytes()],
+        bump
+    )]
+    pub conversation: Account<'info, ConversationState>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConversation<'info> {
+    #[account(mut)]
+    pub conversation: Account<'info, ConversationState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MarkAggregateFailure<'info> {
+    #[account(mut)]
+    pub aggregate: Account<'info, AggregateState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetAggregate<'info> {
+    pub aggregate: Account<'info, AggregateState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(request_id_a: String, request_id_b: String)]
+pub struct CompareResponses<'info> {
+    #[account(
+        seeds = [CONSUMER_STATE_SEED, authority.key().as_ref(), request_id_a.as_bytes()],
+        bump
+    )]
+    pub state_a: Account<'info, ConsumerState>,
+    
+    #[account(
+        seeds = [CONSUMER_STATE_SEED, authority.key().as_ref(), request_id_b.as_bytes()],
+        bump
+    )]
+    pub state_b: Account<'info, ConsumerState>,
+    
+    pub authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct GetResponse<'info> {
     pub consumer_state: Account<'info, ConsumerState>,
@@ -167,12 +1059,135 @@ pub struct ConsumerState {
     pub response: Vec<u8>,
     pub has_response: bool,
     pub authority: Pubkey,
+    pub attestation: Option<OracleAttestation>,
+    pub conversation_hash: [u8; 32],
+    pub min_len: u32,
+    pub max_len: u32,
+    pub require_utf8: bool,
+    pub served_model_id: String,
+    pub response_kind: ResponseKind,
+    pub pending_response_hash: Option<[u8; 32]>,
+    pub detected_format: u8,
+    /// How to decode `response`: `CONTENT_TYPE_TEXT`, `CONTENT_TYPE_JSON` or
+    /// `CONTENT_TYPE_BINARY`, as declared by the fulfilling oracle.
+    pub content_type: u8,
+    pub history_mode: bool,
+    /// Oldest first. Only filled in history mode.
+    pub history: Vec<HistoryEntry>,
+}
+
+#[account]
+pub struct AggregateState {
+    pub aggregate_id: String,
+    pub authority: Pubkey,
+    pub required_count: u8,
+    pub received_count: u8,
+    pub failed_count: u8,
+    pub complete: bool,
+    pub entries: Vec<AggregateEntry>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ResponseComparison {
+    pub hashes_match: bool,
+    pub common_prefix_len: u32,
+    pub len_a: u32,
+    pub len_b: u32,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct HistoryEntry {
+    pub request_id: String,
+    pub response: Vec<u8>,
+    pub received_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AggregateEntry {
+    pub request_id: String,
+    pub response: Vec<u8>,
+    pub has_response: bool,
+    pub failed: bool,
+}
+
+#[account]
+pub struct ConversationState {
+    pub conversation_id: String,
+    pub authority: Pubkey,
+    pub messages: Vec<ConversationMessage>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConversationMessage {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum ResponseKind {
+    Text,
+    ToolCall,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ToolCall {
+    pub tool_name: String,
+    pub arguments: Vec<u8>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RouterResponseBuffer {
+    pub request: Pubkey,
+    pub response: Vec<u8>,
+    pub metadata: ResponseMeta,
+    pub oracle: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OracleAttestation {
+    pub oracle: Pubkey,
+    pub signature: [u8; 64],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct HashTally {
+    pub response_hash: [u8; 32],
+    pub count: u16,
+    pub weight: u64,
 }
 
 #[event]
 pub struct ResponseReceived {
     pub request_id: String,
     pub response_preview: String,
+    pub content_type: u8,
+}
+
+#[event]
+pub struct ResponseAvailable {
+    pub request_id: String,
+    pub response_hash: [u8; 32],
+    pub response_length: u64,
+}
+
+#[event]
+pub struct ContextCompressed {
+    pub conversation_id: String,
+    pub replaced: u8,
+    pub remaining: u8,
+}
+
+#[event]
+pub struct AllResponsesReady {
+    pub aggregate_id: String,
+    pub received_count: u8,
+    pub failed_count: u8,
+}
+
+#[event]
+pub struct ResponseRejected {
+    pub request_id: String,
+    pub error_code: u32,
 }
 
 #[error_code]
@@ -191,4 +1206,71 @@ pub enum ErrorCode {
     InvalidMinVotes,
     #[msg("Approval threshold must be between 1 and 100")]
     InvalidApprovalThreshold,
+    #[msg("Response does not belong to the requested conversation")]
+    ConversationHashMismatch,
+    #[msg("Response length bounds are invalid")]
+    InvalidResponseBounds,
+    #[msg("Response is shorter than the configured minimum")]
+    ResponseTooShortConsumer,
+    #[msg("Response is longer than the configured maximum")]
+    ResponseTooLongConsumer,
+    #[msg("Response is not valid UTF-8")]
+    ResponseNotUtf8,
+    #[msg("Model ID exceeds 64 characters")]
+    ModelIdTooLong,
+    #[msg("Response kind does not match the requested kind")]
+    ResponseKindMismatch,
+    #[msg("Tool call response could not be decoded")]
+    MalformedToolCall,
+    #[msg("Response buffer is not a valid CoolRouter buffer")]
+    InvalidResponseBuffer,
+    #[msg("Pulled response does not match the notified hash")]
+    ResponseHashMismatch,
+    #[msg("Aggregate request list or required count is invalid")]
+    InvalidAggregate,
+    #[msg("Request is not registered in the aggregate")]
+    RequestNotInAggregate,
+    #[msg("Not enough aggregated responses have arrived")]
+    AggregateIncomplete,
+    #[msg("Authority balance cannot cover the request reward")]
+    InsufficientBalanceForReward,
+    #[msg("Conversation message role or content is too long")]
+    MessageTooLong,
+    #[msg("Conversation is full; compress it before adding messages")]
+    ConversationFull,
+    #[msg("Compression must replace between one and all existing messages")]
+    InvalidCompression,
+    #[msg("Content type must be 0 (text), 1 (JSON) or 2 (binary)")]
+    InvalidContentType,
+    #[msg("Consumer state was not created in history mode")]
+    NotHistoryState,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(request_id: &str, received_at: i64) -> HistoryEntry {
+        HistoryEntry {
+            request_id: request_id.to_string(),
+            response: request_id.as_bytes().to_vec(),
+            received_at,
+        }
+    }
+
+    #[test]
+    fn history_evicts_oldest_when_full() {
+        let mut history = Vec::new();
+        for i in 0..MAX_HISTORY_ENTRIES as i64 {
+            push_history_entry(&mut history, entry(&format!("req-{}", i), i));
+        }
+        assert_eq!(history.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(history[0].request_id, "req-0");
+        
+        push_history_entry(&mut history, entry("req-next", 100));
+        assert_eq!(history.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(history[0].request_id, "req-1");
+        assert_eq!(history.last().unwrap().request_id, "req-next");
+        assert!(history.iter().all(|entry| entry.request_id != "req-0"));
+    }
 }
\ No newline at end of file
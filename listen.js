@@ -110,9 +110,22 @@ function loadKeypair(filepath) {
   return Keypair.fromSecretKey(Uint8Array.from(keypairData));
 }
 
-function computeResponseHash(responseText) {
-  const responseBuffer = Buffer.from(responseText);
-  return createHash("sha256").update(responseBuffer).digest();
+function borshBytes(value) {
+  const bytes = Buffer.from(value);
+  const length = Buffer.alloc(4);
+  length.writeUInt32LE(bytes.length);
+  return Buffer.concat([length, bytes]);
+}
+
+// sha256 of the Borsh encoding of (request_id, response, { model_id, finish_reason }),
+// matching coolrouter_cpi::canonical_response_bytes.
+function computeResponseHash(requestId, responseText, metadata) {
+  return createHash("sha256")
+    .update(borshBytes(requestId))
+    .update(borshBytes(responseText))
+    .update(borshBytes(metadata.model_id))
+    .update(borshBytes(metadata.finish_reason))
+    .digest();
 }
 
 class OracleNode {
@@ -248,18 +261,21 @@ class OracleNode {
           event[field.name] = parser.readPubkey();
         } else if (field.type === "u8") {
           event[field.name] = parser.readU8();
+        } else if (field.type === "u64") {
+          event[field.name] = Number(parser.readU64());
         } else if (field.type.vec?.defined?.name === "Message") {
           event[field.name] = parser.readStructVec(this.eventFields.message);
         }
       }
 
-      console.log(`\n[RequestCreated] ${event.request_id}`);
+      console.log(`\n[RequestCreated #${event.seq}] ${event.request_id}`);
       console.log(`  Provider: ${event.provider}, Model: ${event.model_id}`);
       console.log(`  Min Votes: ${event.min_votes}, Threshold: ${event.approval_threshold}%`);
       console.log(`  Slot: ${slot}, Signature: ${signature}`);
 
       this.pendingRequests.set(event.request_id, {
         caller_program: event.caller_program,
+        model_id: event.model_id,
         messages: event.messages,
         min_votes: event.min_votes,
         approval_threshold: event.approval_threshold,
@@ -288,7 +304,9 @@ class OracleNode {
       const event = {};
 
       for (const field of this.eventFields.votingCompleted) {
-        if (field.type === "string") {
+        if (field.type === "u64" && field.name === "seq") {
+          event[field.name] = Number(parser.readU64());
+        } else if (field.type === "string") {
           event[field.name] = parser.readString();
         } else if (field.type.array && field.type.array[0] === "u8" && field.type.array[1] === 32) {
           event[field.name] = parser.readBytes(32);
@@ -297,7 +315,7 @@ class OracleNode {
         }
       }
 
-      console.log(`\n[VotingCompleted] ${event.request_id}`);
+      console.log(`\n[VotingCompleted #${event.seq}] ${event.request_id}`);
       console.log(`  Winning Hash: ${event.winning_hash.toString('hex')}`);
       console.log(`  Votes: ${event.vote_count}/${event.total_votes}`);
       console.log(`  Slot: ${slot}, Signature: ${signature}`);
@@ -337,7 +355,7 @@ class OracleNode {
         }
       }
 
-      console.log(`\n[RequestFulfilled] ${event.request_id}`);
+      console.log(`\n[RequestFulfilled #${event.seq}] ${event.request_id}`);
       console.log(`  Response Length: ${event.response_length} bytes`);
       console.log(`  Slot: ${slot}, Signature: ${signature}`);
 
@@ -377,11 +395,13 @@ class OracleNode {
   async submitVote(requestId, messages) {
     try {
       const llmResponse = "Joe Mama Deez Nuts";
-      const responseHash = computeResponseHash(llmResponse);
-
       const requestData = this.pendingRequests.get(requestId);
+      const metadata = { model_id: requestData?.model_id ?? "", finish_reason: "stop" };
+      const responseHash = computeResponseHash(requestId, llmResponse, metadata);
+
       if (requestData) {
         requestData.myResponse = llmResponse;
+        requestData.myMetadata = metadata;
         requestData.myHash = responseHash;
       }
 